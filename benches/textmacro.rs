@@ -0,0 +1,22 @@
+use std::collections::HashMap;
+use criterion::{criterion_group, criterion_main, Criterion};
+use macroscript::{Macro, TextMacro, apply_macros, add_stdlib};
+
+fn textmacro(c: &mut Criterion) {
+	let square = TextMacro::new("[multiply/$1/$1]");
+	c.bench_function("apply simple", |b| b.iter(|| square.apply(vec!["4"])));
+
+	let long = TextMacro::new(r"[if/[equal/$#/0]/\$0 is empty/$1, $2, $3 and $0]".repeat(16));
+	c.bench_function("apply long", |b| b.iter(|| long.apply(vec!["a", "b", "c", "d"])));
+
+	c.bench_function("new and apply", |b| b.iter(|| TextMacro::new("[multiply/$1/$1]").apply(vec!["4"])));
+
+	let mut macros = HashMap::new();
+	add_stdlib(&mut macros);
+	let items = (0..100).map(|n| n.to_string()).collect::<Vec<_>>().join("/");
+	let script = format!(r"[map/\[add\/$1\/1\]/{items}]");
+	c.bench_function("map over 100 items", |b| b.iter(|| apply_macros(script.clone(), &macros)));
+}
+
+criterion_group!(benches, textmacro);
+criterion_main!(benches);
@@ -1,7 +1,14 @@
 //! Contains items pertaining to execution of macros on a given string.
-use crate::parsing;
-use std::collections::HashMap;
+pub use crate::parsing::MacroRange;
+use crate::{
+	parsing, policy::{Capability, Policy}, locale::{MessageCatalog, English},
+	output::{Output, OutputSink, LogLevel, Warning}, intercept::Interceptor,
+	profile::{Profile, Profiler}, trace::{ExpansionTree, Tracer}, number::NumberMode, TextMacro
+};
+use std::{borrow::Cow, collections::HashMap, ops::Range, time::Instant};
+use itertools::Itertools;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// An error that can arise from a macro.
 pub struct MacroError {
@@ -29,36 +36,268 @@ impl MacroErrorKind {
 	/// Creates an error about not having enough arguments.
 	#[must_use]
 	pub fn not_enough_args(expected: usize, found: usize) -> Self {
-		MacroErrorKind::NotEnoughArguments { expected, found }
+		MacroErrorKind::NotEnoughArguments { expected, found, missing: None }
+	}
+
+	/// Creates an error about a named parameter not having an argument.
+	#[must_use]
+	pub fn missing_parameter(expected: usize, found: usize, parameter: impl Into<String>) -> Self {
+		MacroErrorKind::NotEnoughArguments { expected, found, missing: Some(parameter.into()) }
+	}
+
+	/// Creates an error about having too many arguments.
+	#[must_use]
+	pub fn too_many_args(expected: usize, found: usize) -> Self {
+		MacroErrorKind::TooManyArguments { expected, found }
+	}
+
+	/// Creates an error about an argument failing to convert to another type.
+	#[must_use]
+	pub fn conversion(index: usize, value: impl Into<String>, target: &'static str) -> Self {
+		MacroErrorKind::Conversion { index, value: value.into(), target: Cow::Borrowed(target) }
+	}
+
+	/// Creates an error about an index being out of bounds.
+	#[must_use]
+	pub fn out_of_bounds(index: usize, len: usize) -> Self {
+		MacroErrorKind::OutOfBounds { index, len }
+	}
+
+	/// A short identifier of this kind of error, as passed to `catch` handlers and used to filter errors.
+	///
+	/// For [`MacroErrorKind::Thrown`], this is the custom kind.
+	#[must_use]
+	pub fn identifier(&self) -> &str {
+		match self {
+			MacroErrorKind::NotEnoughArguments { .. } => "not_enough_arguments",
+			MacroErrorKind::TooManyArguments { .. } => "too_many_arguments",
+			MacroErrorKind::Nonexistent { .. } => "nonexistent",
+			MacroErrorKind::User { .. } => "user",
+			MacroErrorKind::NotPermitted => "not_permitted",
+			MacroErrorKind::QuotaExceeded { .. } => "quota_exceeded",
+			MacroErrorKind::Conversion { .. } => "conversion",
+			MacroErrorKind::OutOfBounds { .. } => "out_of_bounds",
+			MacroErrorKind::RangeOutOfBounds { .. } => "range_out_of_bounds",
+			MacroErrorKind::InvalidRegex { .. } => "invalid_regex",
+			MacroErrorKind::LimitExceeded { .. } => "limit_exceeded",
+			MacroErrorKind::UndefinedVariable { .. } => "undefined_variable",
+			MacroErrorKind::ConditionsExhausted => "conditions_exhausted",
+			MacroErrorKind::InvalidBase { .. } => "invalid_base",
+			MacroErrorKind::RadixConversion { .. } => "radix_conversion",
+			MacroErrorKind::InvalidCodepoint { .. } => "invalid_codepoint",
+			MacroErrorKind::ZeroStep => "zero_step",
+			MacroErrorKind::NothingCaught => "nothing_caught",
+			MacroErrorKind::Thrown { kind, .. } => kind,
+			MacroErrorKind::CannotSuspend => "cannot_suspend",
+			MacroErrorKind::InvalidLogLevel { .. } => "invalid_log_level",
+			MacroErrorKind::RecursionLimit { .. } => "recursion_limit"
+		}
 	}
 }
 
+/// A limit that a value can exceed, used by [`MacroErrorKind::LimitExceeded`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Limit {
+	/// The amount of bits to shift an integer by.
+	ShiftAmount,
+	/// The size of a compiled regular expression, in bytes.
+	RegexSize,
+	/// How deeply evaluations run by macros like `map`, and calls made through `call` and `apply`, are nested inside each other.
+	Nesting
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[allow(missing_docs)]
-/// A kind of error that can occur when a macro is executed.
+/**
+A kind of error that can occur when a macro is executed.
+
+Errors carry structured data where possible, and display as the message returned by `try`.
+
+## Example
+```
+#    use macroscript::{apply_macros, add_stdlib, MacroErrorKind};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let err = apply_macros("[add/1/a]".into(), &macros).unwrap_err();
+assert_eq!(MacroErrorKind::conversion(2, "a", "f64"), err.error_type);
+assert_eq!("could not convert argument 2 \"a\" to f64", err.error_type.to_string());
+let err = apply_macros("[select/3/a/b]".into(), &macros).unwrap_err();
+assert_eq!(MacroErrorKind::out_of_bounds(3, 3), err.error_type);
+let err = apply_macros("[multiplu/2/3]".into(), &macros).unwrap_err();
+assert_eq!(MacroErrorKind::Nonexistent { suggestions: vec!["multiply".into()] }, err.error_type);
+assert_eq!("error in macro multiplu: not found, did you mean \"multiply\"?", err.to_string());
+let err = apply_macros("[x]".into(), &macros).unwrap_err();
+assert_eq!(MacroErrorKind::Nonexistent { suggestions: vec![] }, err.error_type);
+#        Ok(()) }
+```
+*/
 pub enum MacroErrorKind {
-	/// Not enough arguments were supplied.
-	NotEnoughArguments { expected: usize, found: usize },
-	/// A macro didn't exist.
-	Nonexistent,
+	/// Not enough arguments were supplied. Contains the name of the first missing parameter, if the macro names them.
+	NotEnoughArguments { expected: usize, found: usize, missing: Option<String> },
+	/// Too many arguments were supplied.
+	TooManyArguments { expected: usize, found: usize },
+	/// A macro didn't exist. Contains the names of existing macros that are close to it, closest first.
+	Nonexistent { suggestions: Vec<String> },
 	/// An error was thrown in the macro.
-	User { message: String }
+	User { message: String },
+	/// The macro isn't allowed to be called by the current [`Policy`].
+	NotPermitted,
+	/// The macro was called more times than the current [`Policy`] allows.
+	QuotaExceeded { limit: usize },
+	/// An argument couldn't be converted to another type. The index is one-based.
+	Conversion { index: usize, value: String, target: Cow<'static, str> },
+	/// An index was out of bounds of a list with the given length.
+	OutOfBounds { index: usize, len: usize },
+	/// A range was partially out of bounds of a string with the given length.
+	RangeOutOfBounds { start: Option<usize>, end: Option<usize>, len: usize },
+	/// A regular expression failed to compile.
+	InvalidRegex { detail: String },
+	/// A value exceeded a limit. For [`Limit::ShiftAmount`], this is the value itself,
+	/// and for [`Limit::RegexSize`] and [`Limit::Nesting`], this is the limit that was exceeded.
+	LimitExceeded { limit: Limit, value: usize },
+	/// A variable was loaded that doesn't exist. Contains the names of existing variables that are close to it, closest first.
+	UndefinedVariable { name: String, suggestions: Vec<String> },
+	/// None of the conditions of an `if` were truthy, and there was no base case.
+	ConditionsExhausted,
+	/// A numeric base wasn't between 2 and 36, inclusive.
+	InvalidBase { base: u32 },
+	/// A value couldn't be converted to an integer with the given base.
+	RadixConversion { value: String, base: u32 },
+	/// An argument wasn't a valid unicode codepoint. The index is one-based.
+	InvalidCodepoint { index: usize },
+	/// A step length of 0 was given.
+	ZeroStep,
+	/// `rethrow` was called before any error was caught.
+	NothingCaught,
+	/// An error with a custom kind was thrown by the script, e.g. by `throw`.
+	Thrown { kind: String, payload: String },
+	/// A macro asked for input, but the evaluation isn't [suspendable](Evaluation::suspendable).
+	CannotSuspend,
+	/// A log level wasn't one of `debug`, `info`, `warn` or `error`.
+	InvalidLogLevel { level: String },
+	/// A macro was nested inside itself more deeply than the [engine](Engine::with_recursion_limit) allows.
+	/// Contains the calls from the innermost enclosing call to the same macro down to the one that failed.
+	RecursionLimit { limit: usize, cycle: Vec<String> }
 }
 
 impl std::fmt::Display for MacroErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		use MacroErrorKind::*;
+		use MacroErrorKind::{
+			NotEnoughArguments, TooManyArguments, Nonexistent, User, NotPermitted, QuotaExceeded,
+			Conversion, OutOfBounds, RangeOutOfBounds, InvalidRegex, LimitExceeded,
+			UndefinedVariable, ConditionsExhausted, InvalidBase, RadixConversion, InvalidCodepoint, ZeroStep,
+			NothingCaught, Thrown, CannotSuspend, InvalidLogLevel, RecursionLimit
+		};
 		match self {
-			NotEnoughArguments { expected, found } =>
+			NotEnoughArguments { expected, found, missing: None } =>
 				write!(f, "expected {expected} arguments, found {found}"),
-			Nonexistent =>
-				write!(f, "not found"),
+			NotEnoughArguments { expected, found, missing: Some(parameter) } =>
+				write!(f, "expected {expected} arguments, found {found}, missing \"{parameter}\""),
+			TooManyArguments { expected, found } =>
+				write!(f, "expected at most {expected} arguments, found {found}"),
+			Nonexistent { suggestions } => {
+				write!(f, "not found")?;
+				write_suggestions(f, suggestions)
+			},
 			User { message } =>
-				write!(f, "{message}") 
+				write!(f, "{message}"),
+			NotPermitted =>
+				write!(f, "not permitted"),
+			QuotaExceeded { limit } =>
+				write!(f, "called more than {limit} times"),
+			Conversion { index, value, target } =>
+				write!(f, "could not convert argument {index} \"{value}\" to {target}"),
+			OutOfBounds { index, .. } =>
+				write!(f, "index {index} is out of bounds"),
+			RangeOutOfBounds { start, end, len } => write!(
+				f, "part of range \"{}..{}\" is out of bounds for string of length {len}",
+				start.map(|v| v.to_string()).unwrap_or_default(),
+				end.map(|v| v.to_string()).unwrap_or_default()
+			),
+			InvalidRegex { detail } =>
+				write!(f, "{detail}"),
+			LimitExceeded { limit: Limit::ShiftAmount, value } =>
+				write!(f, "shift amount of {value} is too large"),
+			LimitExceeded { limit: Limit::RegexSize, value } =>
+				write!(f, "compiled regex exceeds size limit of {value} bytes"),
+			LimitExceeded { limit: Limit::Nesting, value } =>
+				write!(f, "nesting exceeds limit of {value}"),
+			UndefinedVariable { name, suggestions } => {
+				write!(f, "variable \"{name}\" does not currently exist")?;
+				write_suggestions(f, suggestions)
+			},
+			ConditionsExhausted =>
+				write!(f, "all conditions exhausted"),
+			InvalidBase { base } =>
+				write!(f, "invalid base {base} (must be between 2 and 36, inclusive)"),
+			RadixConversion { value, base } =>
+				write!(f, "failed to convert {value} to a number with base {base}"),
+			InvalidCodepoint { index } =>
+				write!(f, "invalid codepoint at argument {index}"),
+			ZeroStep =>
+				write!(f, "cannot have a step length of 0"),
+			NothingCaught =>
+				write!(f, "no error has been caught"),
+			Thrown { kind, payload } if payload.is_empty() =>
+				write!(f, "{kind}"),
+			Thrown { kind, payload } =>
+				write!(f, "{kind}: {payload}"),
+			CannotSuspend =>
+				write!(f, "cannot ask for input here"),
+			InvalidLogLevel { level } =>
+				write!(f, "invalid log level \"{level}\""),
+			RecursionLimit { limit, cycle } =>
+				write!(f, "nested more than {limit} times in itself, through {}", cycle.join(" -> "))
 		}
 	}	
 }
 
+/// Writes a list of suggested names, if there are any.
+fn write_suggestions(f: &mut std::fmt::Formatter<'_>, suggestions: &[String]) -> std::fmt::Result {
+	match suggestions {
+		[] => Ok(()),
+		[single] => write!(f, ", did you mean \"{single}\"?"),
+		multiple => write!(f, ", did you mean one of {}?", multiple.iter().map(|name| format!("\"{name}\"")).join(", "))
+	}
+}
+
+/// Finds the names closest to the given one by edit distance, closest first.
+///
+/// Only names within a third of the name's length are considered, and at most 3 are returned.
+/// The empty name is never suggested.
+fn suggestions<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+	let threshold = (name.chars().count() / 3).max(1);
+	candidates.into_iter()
+		.filter(|candidate| !candidate.is_empty() && *candidate != name)
+		.map(|candidate| (edit_distance(name, candidate), candidate))
+		.filter(|(distance, _)| *distance <= threshold)
+		.sorted()
+		.take(3)
+		.map(|(_, candidate)| candidate.to_string())
+		.collect()
+}
+
+/// Finds the Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut row: Vec<usize> = (0 ..= b.len()).collect();
+	for (i, a_char) in a.chars().enumerate() {
+		let mut diagonal = row[0];
+		row[0] = i + 1;
+		for (j, b_char) in b.iter().enumerate() {
+			let substitution = diagonal + usize::from(a_char != *b_char);
+			diagonal = row[j + 1];
+			row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+		}
+	}
+	row[b.len()]
+}
+
 impl std::error::Error for MacroError {}
 
 impl std::fmt::Display for MacroError {
@@ -73,171 +312,1601 @@ pub trait Macro {
     ///
     /// # Errors
     /// If the macro fails to apply, an error will be raised with a message.
+    /// If the error has an empty name, the engine replaces it with the name the macro was called with.
     fn apply(
         &self,
         arguments: Vec<&str>,
     ) -> Result<String, MacroError>;
+
+    /// Applies this macro with access to the evaluation calling it, which can evaluate macroscript.
+    ///
+    /// The engine calls this instead of [`Macro::apply`].
+    /// By default, this ignores the context and calls [`Macro::apply`].
+    ///
+    /// # Errors
+    /// Errors in the same way as [`Macro::apply`].
+    /// Errors from evaluating macroscript through the context can be returned as they are.
+    fn apply_in(
+        &self,
+        context: &mut dyn Context,
+        arguments: Vec<&str>,
+    ) -> Result<String, MacroError> {
+        let _ = context;
+        self.apply(arguments)
+    }
+
+    /// The capabilities this macro needs, checked against the [`Policy`] before it's applied.
+    ///
+    /// By default, a macro needs no capabilities.
+    fn capabilities(&self) -> &'static [Capability] {
+        &[]
+    }
+}
+
+/**
+The evaluation a macro is called from, given to [`Macro::apply_in`].
+
+This lets macros such as `filter` evaluate the results of text macros before using them.
+
+## Example
+```
+#    use macroscript::{Engine, Macro, MacroError, MacroErrorKind, Context, add_stdlib};
+#    use std::collections::HashMap;
+#
+struct Tick;
+
+impl Macro for Tick {
+    fn apply(&self, _arguments: Vec<&str>) -> Result<String, MacroError> {
+        Err(MacroError::new(String::new(), MacroErrorKind::User { message: "needs an engine".into() }))
+    }
+
+    fn apply_in(&self, context: &mut dyn Context, _arguments: Vec<&str>) -> Result<String, MacroError> {
+        context.evaluate("[store/ticks/[add/[get/ticks/0]/1]]")?;
+        context.evaluate("[load/ticks]")
+    }
+}
+
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+macros.insert("tick".into(), Box::new(Tick) as Box<dyn Macro>);
+let engine = Engine::new(&macros);
+assert_eq!("1, 2, 3", engine.apply("[tick], [tick], [tick]".into())?);
+#        Ok(()) }
+```
+*/
+pub trait Context {
+	/// Evaluates a string of macroscript to completion, sharing variables with the calling evaluation.
+	///
+	/// The string isn't unescaped first.
+	/// Calls made this way go through the engine's policy and interceptors,
+	/// and count towards the [recursion limit](Engine::with_recursion_limit),
+	/// but can't ask for input, and their time is profiled as part of the calling macro.
+	///
+	/// Evaluations can be nested inside each other at most 64 times, whatever the recursion limit is,
+	/// since each one runs on the native stack. Going deeper fails with [`MacroErrorKind::LimitExceeded`].
+	///
+	/// ## Example
+	/// ```
+	/// #    use macroscript::{Engine, Limit, MacroErrorKind, TextMacro, add_stdlib};
+	/// #    use std::collections::HashMap;
+	/// #
+	/// let mut macros = HashMap::new();
+	/// add_stdlib(&mut macros);
+	/// macros.insert("reme".into(), TextMacro::boxed(r"[filter/\[reme\/$1\]/a]"));
+	/// let err = Engine::new(&macros).apply("[reme/1]".into()).unwrap_err();
+	/// assert_eq!(MacroErrorKind::LimitExceeded { limit: Limit::Nesting, value: 64 }, err.error_type);
+	/// assert_eq!("error in macro filter: nesting exceeds limit of 64", err.to_string());
+	/// ```
+	///
+	/// # Errors
+	/// Errors if any macro in the string errors, and the error isn't caught.
+	fn evaluate(&mut self, input: &str) -> Result<String, MacroError>;
+
+	/// How numeric macros should compute. See [`NumberMode`].
+	///
+	/// By default, this is [`NumberMode::Float`].
+	fn number_mode(&self) -> NumberMode {
+		NumberMode::Float
+	}
+}
+
+/// One of the macros that are built into the engine itself, rather than the macro registry.
+///
+/// See the documentation of [`crate::stdlib::DocumentationHelper`] for what each of them does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CoreMacro {
+	/// `try`: runs escaped macroscript, catching any errors.
+	Try,
+	/// `catch`: runs escaped macroscript, passing any errors to a handler.
+	Catch,
+	/// `rethrow`: raises the last caught error again.
+	Rethrow,
+	/// `load`: loads a variable.
+	Load,
+	/// `store`: stores a variable.
+	Store,
+	/// `drop`: deletes a variable.
+	Drop,
+	/// `get`: loads a variable, storing a default if it doesn't exist.
+	Get,
+	/// `is_stored`: returns whether a variable exists.
+	IsStored,
+	/// `input`: suspends the evaluation until the host supplies a value.
+	Input,
+	/// `print`: emits text to the host, without affecting the output.
+	Print,
+	/// `log_at`: emits a message with a log level to the host, without affecting the output.
+	///
+	/// This isn't named `log`, since the standard library uses that name for logarithms.
+	Log,
+	/// `lambda`: creates an anonymous macro, returning a handle to call it by.
+	Lambda,
+	/// `call`: calls a macro whose name is given as an argument.
+	Call,
+	/// `apply`: calls a macro with the items of an escaped list as its arguments.
+	Apply,
+	/// `partial`: fixes the first arguments of a macro, returning a handle to call it by.
+	Partial,
+	/// `compose`: chains macros together, returning a handle to call them by.
+	Compose
+}
+
+impl CoreMacro {
+	/// All core macros, in declaration order.
+	pub const ALL: [CoreMacro; 16] = [
+		CoreMacro::Try, CoreMacro::Catch, CoreMacro::Rethrow, CoreMacro::Load,
+		CoreMacro::Store, CoreMacro::Drop, CoreMacro::Get, CoreMacro::IsStored,
+		CoreMacro::Input, CoreMacro::Print, CoreMacro::Log, CoreMacro::Lambda,
+		CoreMacro::Call, CoreMacro::Apply, CoreMacro::Partial, CoreMacro::Compose
+	];
+
+	/// The name this core macro is available under by default.
+	#[must_use]
+	pub fn default_name(self) -> &'static str {
+		match self {
+			CoreMacro::Try => "try",
+			CoreMacro::Catch => "catch",
+			CoreMacro::Rethrow => "rethrow",
+			CoreMacro::Load => "load",
+			CoreMacro::Store => "store",
+			CoreMacro::Drop => "drop",
+			CoreMacro::Get => "get",
+			CoreMacro::IsStored => "is_stored",
+			CoreMacro::Input => "input",
+			CoreMacro::Print => "print",
+			CoreMacro::Log => "log_at",
+			CoreMacro::Lambda => "lambda",
+			CoreMacro::Call => "call",
+			CoreMacro::Apply => "apply",
+			CoreMacro::Partial => "partial",
+			CoreMacro::Compose => "compose"
+		}
+	}
+
+	/// The capabilities this core macro needs.
+	///
+	/// `lambda` also needs [`Capability::VariableRead`] when it captures variables.
+	#[must_use]
+	pub fn capabilities(self) -> &'static [Capability] {
+		match self {
+			CoreMacro::Try | CoreMacro::Catch | CoreMacro::Rethrow | CoreMacro::Lambda
+				| CoreMacro::Call | CoreMacro::Apply | CoreMacro::Partial | CoreMacro::Compose => &[],
+			CoreMacro::Load | CoreMacro::IsStored => &[Capability::VariableRead],
+			CoreMacro::Store | CoreMacro::Drop => &[Capability::VariableWrite],
+			CoreMacro::Get => &[Capability::VariableRead, Capability::VariableWrite],
+			CoreMacro::Input => &[Capability::Input],
+			CoreMacro::Print | CoreMacro::Log => &[Capability::Output]
+		}
+	}
+}
+
+/**
+Configures which core macros are available to a script, and under which names.
+
+By default, every [`CoreMacro`] is available under its [default name](CoreMacro::default_name),
+and core macros take priority over any macro in the registry with the same name.
+
+Disabling a core macro makes calls to it fall through to the registry like any other name,
+and renaming one lets a registered macro take its old name while still being able to reach it.
+
+## Example
+```
+#    use macroscript::{Macro, Engine, CoreMacro, CoreMacros, TextMacro, add_stdlib};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::<String, Box<dyn Macro>>::from([
+    // Wraps the renamed core macro
+    ("load".to_string(), TextMacro::boxed("<$1>[raw_load/$1]"))
+]);
+add_stdlib(&mut macros);
+let core = CoreMacros::default()
+    .without(CoreMacro::Store)
+    .renamed(CoreMacro::Load, "raw_load");
+let engine = Engine::new(&macros).with_core_macros(core);
+assert_eq!("5<x>5", engine.apply("[get/x/5][load/x]".into())?);
+let err = engine.apply("[store/x/5]".into()).unwrap_err();
+assert_eq!("error in macro store: not found", err.to_string());
+#        Ok(()) }
+```
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreMacros {
+	names: HashMap<String, CoreMacro>,
+	shadowable: bool
+}
+
+impl Default for CoreMacros {
+	fn default() -> Self {
+		Self {
+			names: CoreMacro::ALL.into_iter()
+				.map(|mac| (mac.default_name().to_string(), mac))
+				.collect(),
+			shadowable: false
+		}
+	}
+}
+
+impl CoreMacros {
+	/// Creates a configuration with no core macros available.
+	#[must_use]
+	pub fn none() -> Self {
+		Self { names: HashMap::new(), shadowable: false }
+	}
+
+	/// Disables a core macro, removing all names it's available under.
+	#[must_use]
+	pub fn without(mut self, mac: CoreMacro) -> Self {
+		self.names.retain(|_, other| *other != mac);
+		self
+	}
+
+	/// Makes a core macro available under an extra name.
+	#[must_use]
+	pub fn with(mut self, mac: CoreMacro, name: impl Into<String>) -> Self {
+		self.names.insert(name.into(), mac);
+		self
+	}
+
+	/// Makes a core macro available only under the given name.
+	#[must_use]
+	pub fn renamed(self, mac: CoreMacro, name: impl Into<String>) -> Self {
+		self.without(mac).with(mac, name)
+	}
+
+	/// Sets whether macros in the registry take priority over core macros with the same name.
+	#[must_use]
+	pub fn shadowable(mut self, shadowable: bool) -> Self {
+		self.shadowable = shadowable;
+		self
+	}
+
+	/// Gets the core macro available under a name, if any.
+	#[must_use]
+	pub fn get(&self, name: &str) -> Option<CoreMacro> {
+		self.names.get(name).copied()
+	}
+}
+
+/// Applies macros from a registry to strings, with extra configuration.
+///
+/// [`apply_macros`] is a shorthand for an engine with the default configuration.
+pub struct Engine<'macros, S = std::collections::hash_map::RandomState> {
+	macros: &'macros HashMap<String, Box<dyn Macro>, S>,
+	core: CoreMacros,
+	policy: Policy,
+	catalog: &'macros dyn MessageCatalog,
+	marker: Option<&'macros dyn Fn(&MacroError) -> String>,
+	sink: Option<&'macros dyn OutputSink>,
+	interceptors: Vec<&'macros dyn Interceptor>,
+	recursion_limit: Option<usize>,
+	number_mode: NumberMode
+}
+
+impl<'macros, S: std::hash::BuildHasher> Engine<'macros, S> {
+	/// Creates an engine using the given macro registry.
+	#[must_use]
+	pub fn new(macros: &'macros HashMap<String, Box<dyn Macro>, S>) -> Self {
+		Self { macros, core: CoreMacros::default(), policy: Policy::default(), catalog: &English, marker: None, sink: None, interceptors: Vec::new(), recursion_limit: None, number_mode: NumberMode::Float }
+	}
+
+	/// Sets which core macros are available, and under which names.
+	#[must_use]
+	pub fn with_core_macros(mut self, core: CoreMacros) -> Self {
+		self.core = core;
+		self
+	}
+
+	/// Sets the policy restricting which macros may be called.
+	#[must_use]
+	pub fn with_policy(mut self, policy: Policy) -> Self {
+		self.policy = policy;
+		self
+	}
+
+	/// Sets the message catalog used to render errors caught by `try`.
+	#[must_use]
+	pub fn with_catalog(mut self, catalog: &'macros dyn MessageCatalog) -> Self {
+		self.catalog = catalog;
+		self
+	}
+
+	/// Sets the function used by [`Engine::apply_recovering`] to render the marker that replaces a failed call.
+	///
+	/// By default, the marker is the error rendered by the message catalog, surrounded by `⟨⟩`.
+	/// Brackets and backslashes in the marker are escaped.
+	#[must_use]
+	pub fn with_error_marker(mut self, marker: &'macros dyn Fn(&MacroError) -> String) -> Self {
+		self.marker = Some(marker);
+		self
+	}
+
+	/// Adds an interceptor that sees every call before and after it runs. See [`Interceptor`].
+	///
+	/// Interceptors run in the order they were added.
+	#[must_use]
+	pub fn with_interceptor(mut self, interceptor: &'macros dyn Interceptor) -> Self {
+		self.interceptors.push(interceptor);
+		self
+	}
+
+	/// Sets the sink that receives side-channel output, such as from `print`, as it happens.
+	///
+	/// By default, each evaluation collects its own output instead. See [`OutputSink`].
+	#[must_use]
+	pub fn with_sink(mut self, sink: &'macros dyn OutputSink) -> Self {
+		self.sink = Some(sink);
+		self
+	}
+
+	/// Sets how many times a macro may be nested inside itself, to stop runaway recursion.
+	///
+	/// A call is nested inside another if it was produced by that call's output,
+	/// or is in the script run by a `try` or `catch`. Calls made through `call` and `apply` count as calls to the macro they ran.
+	/// A call nested more deeply fails with [`MacroErrorKind::RecursionLimit`], which names the cycle it's part of.
+	/// By default, there's no limit, and the ancestry of calls isn't tracked.
+	/// Ancestry isn't kept in a [`Continuation`], so calls made before resuming don't count.
+	///
+	/// ## Example
+	/// ```
+	/// #    use macroscript::{Engine, MacroErrorKind, TextMacro, add_stdlib};
+	/// #    use std::collections::HashMap;
+	/// #
+	/// let mut macros = HashMap::new();
+	/// add_stdlib(&mut macros);
+	/// macros.insert("ping".into(), TextMacro::boxed("[pong/$1]"));
+	/// macros.insert("pong".into(), TextMacro::boxed("[ping/$1]"));
+	/// macros.insert("countdown".into(), TextMacro::boxed(r"$1[unescape/[if/[equal/$1/0]/!/\[countdown\/\[subtract\/$1\/1\]\]]]"));
+	/// let engine = Engine::new(&macros).with_recursion_limit(10);
+	/// assert_eq!("543210!", engine.apply("[countdown/5]".into()).unwrap());
+	/// assert!(Engine::new(&macros).with_recursion_limit(3).apply("[countdown/5]".into()).is_err());
+	/// let err = engine.apply("[ping/x]".into()).unwrap_err();
+	/// assert_eq!(
+	///     MacroErrorKind::RecursionLimit { limit: 10, cycle: vec!["ping".into(), "pong".into(), "ping".into()] },
+	///     err.error_type
+	/// );
+	/// assert_eq!("nested more than 10 times in itself, through ping -> pong -> ping", err.error_type.to_string());
+	/// macros.insert("recall".into(), TextMacro::boxed("[call/recall]"));
+	/// let err = Engine::new(&macros).with_recursion_limit(2).apply("[recall]".into()).unwrap_err();
+	/// assert_eq!("nested more than 2 times in itself, through recall -> recall", err.error_type.to_string());
+	/// ```
+	#[must_use]
+	pub fn with_recursion_limit(mut self, limit: usize) -> Self {
+		self.recursion_limit = Some(limit);
+		self
+	}
+
+	/// Sets how the numeric macros of the standard library compute. See [`NumberMode`].
+	#[must_use]
+	pub fn with_number_mode(mut self, mode: NumberMode) -> Self {
+		self.number_mode = mode;
+		self
+	}
+
+	/// Finds which core macro a name refers to, taking shadowing into account.
+	fn core_macro(&self, name: &str) -> Option<CoreMacro> {
+		let mac = self.core.get(name)?;
+		if self.core.shadowable && self.macros.contains_key(name) {
+			return None;
+		}
+		Some(mac)
+	}
+
+	/// Finds the names of callable macros closest to a name that doesn't exist.
+	fn macro_suggestions(&self, name: &str) -> Vec<String> {
+		let registry = self.macros.iter()
+			.filter(|(candidate, _)| self.core_macro(candidate).is_none())
+			.map(|(candidate, mac)| (candidate.as_str(), mac.capabilities()));
+		let core = self.core.names.keys()
+			.filter_map(|candidate| Some((candidate.as_str(), self.core_macro(candidate)?.capabilities())));
+		let names = registry.chain(core)
+			.filter(|(candidate, capabilities)| self.policy.permits(candidate, capabilities))
+			.map(|(candidate, _)| candidate);
+		suggestions(name, names)
+	}
+
+	/// Applies all found macros in the string until none are left.
+	///
+	/// # Errors
+	/// Errors if any macro in the input errors.
+	pub fn apply(&self, input: String) -> Result<String, MacroError> {
+		self.apply_with_state(input).map_err(|failure| failure.error)
+	}
+
+	/// Applies all found macros in the string until none are left,
+	/// keeping the state of the evaluation if it fails.
+	///
+	/// ## Example
+	/// ```
+	/// #    use macroscript::{Engine, add_stdlib};
+	/// #    use std::collections::HashMap;
+	/// #
+	/// let mut macros = HashMap::new();
+	/// add_stdlib(&mut macros);
+	/// let engine = Engine::new(&macros);
+	/// let failure = engine.apply_with_state("[store/x/5][upper/a][load/y]".into()).unwrap_err();
+	/// assert_eq!("A[load/y]", failure.input);
+	/// assert_eq!(Some("5"), failure.variables.get("x").map(String::as_str));
+	/// assert_eq!(3, failure.steps);
+	/// ```
+	///
+	/// # Errors
+	/// Errors if any macro in the input errors.
+	pub fn apply_with_state(&self, input: String) -> Result<String, Box<Failure>> {
+		let Progress::Finished(output) = self.evaluate(input).run()? else {
+			unreachable!("evaluations that aren't suspendable run until finished")
+		};
+		Ok(output)
+	}
+
+	/// Applies all found macros in the string until none are left, without stopping at errors.
+	///
+	/// Every call that raises an error that isn't caught by `try` or `catch` is replaced with a marker,
+	/// and evaluation continues. The marker can be changed with [`Engine::with_error_marker`].
+	///
+	/// ## Example
+	/// ```
+	/// #    use macroscript::{Engine, add_stdlib};
+	/// #    use std::collections::HashMap;
+	/// #
+	/// let mut macros = HashMap::new();
+	/// add_stdlib(&mut macros);
+	/// let engine = Engine::new(&macros);
+	/// let recovered = engine.apply_recovering("[add/1/1], [split/a/,/3], [upper/ok]".into());
+	/// assert_eq!("2, ⟨error in macro split: index 3 is out of bounds⟩, OK", recovered.output);
+	/// assert_eq!(1, recovered.errors.len());
+	/// assert_eq!(3 .. 16, recovered.errors[0].span);
+	/// let marker = |err: &macroscript::MacroError| format!("<{} failed>", err.name);
+	/// let recovered = engine.with_error_marker(&marker).apply_recovering("[add/[nope]]".into());
+	/// assert_eq!("<add failed>", recovered.output);
+	/// assert_eq!(2, recovered.errors.len());
+	/// ```
+	#[must_use]
+	pub fn apply_recovering(&self, input: String) -> Recovered {
+		let mut evaluation = self.evaluate(input).recovering(true);
+		let Ok(Progress::Finished(output)) = evaluation.run() else {
+			unreachable!("all uncaught errors are recovered from")
+		};
+		Recovered { output, errors: evaluation.recovered }
+	}
+
+	/// Starts evaluating a string, one macro call at a time. See [`Evaluation`].
+	#[must_use]
+	pub fn evaluate(&self, input: String) -> Evaluation<'_, 'macros, S> {
+		Evaluation {
+			engine: self,
+			frames: vec![Frame { input, slot: 0..0, name: String::new(), arguments: Vec::new(), kind: FrameKind::Root }],
+			state: State::default(),
+			recovering: false,
+			recovered: Vec::new(),
+			suspendable: false,
+			suspended: None,
+			profiler: None,
+			tracer: self.recursion_limit.map(|_| Tracer::new(1, false))
+		}
+	}
+
+	/// Continues a paused evaluation. See [`Evaluation::into_continuation`].
+	#[must_use]
+	pub fn resume(&self, continuation: Continuation) -> Evaluation<'_, 'macros, S> {
+		let frames = continuation.frames.len();
+		Evaluation {
+			engine: self,
+			frames: continuation.frames,
+			state: continuation.state,
+			recovering: continuation.recovering,
+			recovered: continuation.recovered,
+			suspendable: continuation.suspendable,
+			suspended: continuation.suspended,
+			profiler: None,
+			tracer: self.recursion_limit.map(|_| Tracer::new(frames, false))
+		}
+	}
+
+	/// Renders the marker for a failed call.
+	fn marker(&self, error: &MacroError) -> String {
+		let marker = match self.marker {
+			Some(marker) => marker(error),
+			None => format!("⟨{}⟩", self.catalog.error(error))
+		};
+		escape(&marker, false)
+	}
+
+	/// Sends side-channel output to the sink, or collects it if there isn't one.
+	fn emit(&self, state: &mut State, output: Output) {
+		match self.sink {
+			Some(sink) => sink.emit(output),
+			None => state.outputs.push(output)
+		}
+	}
+
+	/// Calls a single macro, which was traced as the given node.
+	fn call(
+		&self, state: &mut State, tracer: &mut Option<Tracer>, node: Option<usize>, macro_range: &parsing::MacroRange<'_>
+	) -> Result<Call, MacroError> {
+		state.steps += 1;
+		let name = macro_range.name;
+		let arguments = &macro_range.arguments;
+		let handle = handle_id(name).and_then(|id| state.handles.get(id)).cloned();
+		let core = match handle {
+			Some(_) => None,
+			None => self.core_macro(name)
+		};
+		let mac: Option<&dyn Macro> = match (&handle, core) {
+			(Some(handle), _) => Some(handle),
+			(None, Some(_)) => None,
+			(None, None) => Some(self.macros.get(name).ok_or_else(|| MacroError::new(
+				name.into(), MacroErrorKind::Nonexistent { suggestions: self.macro_suggestions(name) }
+			))?.as_ref())
+		};
+		// Handles are always permitted, since creating them already passed the policy
+		if handle.is_none() {
+			let capabilities = match (core, mac) {
+				(Some(core), _) => core.capabilities(),
+				(None, Some(mac)) => mac.capabilities(),
+				(None, None) => &[]
+			};
+			if !self.policy.permits(name, capabilities) {
+				return Err(MacroError::new(name.into(), MacroErrorKind::NotPermitted));
+			}
+			if let Some(limit) = self.policy.quota_of(name) {
+				let count = state.calls.entry(name.to_string()).or_default();
+				if *count >= limit {
+					return Err(MacroError::new(name.into(), MacroErrorKind::QuotaExceeded { limit }));
+				}
+				*count += 1;
+			}
+		}
+		let intercepted = self.interceptors.iter().find_map(|interceptor| interceptor.before(name, arguments));
+		let result = match intercepted {
+			Some(result) => result.map(Call::Replace),
+			None => self.dispatch(Nested { engine: self, state, tracer, node }, core, mac, macro_range)
+		};
+		// Calls that push a frame are seen after that frame is done
+		match result {
+			Ok(Call::Replace(value)) => self.after(name, arguments, Ok(value)).map(Call::Replace),
+			Err(err) => self.after(name, arguments, Err(err)).map(Call::Replace),
+			call => call
+		}
+	}
+
+	/// Runs the after hooks of every interceptor on the result of a call.
+	fn after(&self, name: &str, arguments: &[&str], result: Result<String, MacroError>) -> Result<String, MacroError> {
+		self.interceptors.iter().rev().fold(result, |result, interceptor| interceptor.after(name, arguments, result))
+	}
+
+	/// Runs the after hooks of every interceptor on the result of a frame's call.
+	fn after_frame(&self, frame: &Frame, result: String) -> Result<String, MacroError> {
+		let arguments: Vec<&str> = frame.arguments.iter().map(String::as_str).collect();
+		self.after(&frame.name, &arguments, Ok(result))
+	}
+
+	/// Calls a single macro that has passed all checks, either from the registry or the core.
+	fn dispatch(
+		&self, mut context: Nested<'_, '_, 'macros, S>, core: Option<CoreMacro>, mac: Option<&dyn Macro>, macro_range: &parsing::MacroRange<'_>
+	) -> Result<Call, MacroError> {
+		let name = macro_range.name;
+		let arguments = &macro_range.arguments;
+		let not_enough = |expected| MacroError::new(
+			name.into(), MacroErrorKind::not_enough_args(expected, arguments.len())
+		);
+		let Some(core) = core else {
+			let mac = mac.expect("registry macro was found above");
+			return mac.apply_in(&mut context, arguments.clone()).map(Call::Replace).map_err(|mut err| {
+				if err.name.is_empty() {
+					err.name = name.into();
+				}
+				err
+			});
+		};
+		let Nested { state, tracer, node, .. } = context;
+		match core {
+			CoreMacro::Try => {
+				let [body, ref kinds @ ..] = arguments[..] else { return Err(not_enough(1)) };
+				Ok(Call::Push(Frame {
+					input: parsing::unescape(body).into_owned(),
+					slot: macro_range.range.clone(),
+					name: name.to_string(),
+					arguments: arguments.iter().map(ToString::to_string).collect(),
+					kind: FrameKind::Try { kinds: kinds.iter().map(ToString::to_string).collect() }
+				}))
+			}
+			CoreMacro::Catch => {
+				let [body, handler, ref kinds @ ..] = arguments[..] else { return Err(not_enough(2)) };
+				Ok(Call::Push(Frame {
+					input: parsing::unescape(body).into_owned(),
+					slot: macro_range.range.clone(),
+					name: name.to_string(),
+					arguments: arguments.iter().map(ToString::to_string).collect(),
+					kind: FrameKind::Catch {
+						handler: parsing::unescape(handler).into_owned(),
+						kinds: kinds.iter().map(ToString::to_string).collect()
+					}
+				}))
+			}
+			CoreMacro::Rethrow => Err(state.caught.clone().ok_or_else(|| MacroError::new(
+				name.into(), MacroErrorKind::NothingCaught
+			))?),
+			CoreMacro::Load => {
+				let [var, ..] = arguments[..] else { return Err(not_enough(1)) };
+				state.variables.get(var).cloned().map(Call::Replace).ok_or_else(|| undefined_variable(name, var, state))
+			}
+			CoreMacro::Drop => {
+				let [var, ..] = arguments[..] else { return Err(not_enough(1)) };
+				state.variables.remove(var);
+				Ok(Call::Replace(String::new()))
+			}
+			CoreMacro::Store => {
+				let [var, value, ..] = arguments[..] else { return Err(not_enough(2)) };
+				state.variables.insert(var.to_string(), value.to_string());
+				Ok(Call::Replace(String::new()))
+			}
+			CoreMacro::Get => {
+				let [var, value, ..] = arguments[..] else { return Err(not_enough(2)) };
+				let result = state.variables
+					.entry(var.to_string())
+					.or_insert(value.to_string());
+				Ok(Call::Replace(result.clone()))
+			}
+			CoreMacro::IsStored => {
+				let [var, ..] = arguments[..] else { return Err(not_enough(1)) };
+				Ok(Call::Replace(state.variables.contains_key(var).to_string()))
+			}
+			CoreMacro::Input => Ok(Call::Suspend(Suspension {
+				name: name.to_string(),
+				arguments: arguments.iter().map(ToString::to_string).collect()
+			})),
+			CoreMacro::Print => {
+				self.emit(state, Output::Print(arguments.join("/")));
+				Ok(Call::Replace(String::new()))
+			}
+			CoreMacro::Log => {
+				let [level, ref message @ ..] = arguments[..] else { return Err(not_enough(1)) };
+				let level = level.parse::<LogLevel>().map_err(|()| MacroError::new(
+					name.into(), MacroErrorKind::InvalidLogLevel { level: level.to_string() }
+				))?;
+				self.emit(state, Output::Log { level, message: message.join("/") });
+				Ok(Call::Replace(String::new()))
+			}
+			CoreMacro::Lambda => {
+				let [ref params @ .., body] = arguments[..] else { return Err(not_enough(1)) };
+				let (mut names, mut captures) = (Vec::new(), Vec::new());
+				for param in params {
+					let Some(var) = param.strip_prefix('&') else {
+						names.push((*param).to_string());
+						continue;
+					};
+					if !self.policy.permits(name, &[Capability::VariableRead]) {
+						return Err(MacroError::new(name.into(), MacroErrorKind::NotPermitted));
+					}
+					let value = state.variables.get(var).ok_or_else(|| undefined_variable(name, var, state))?;
+					captures.push((var.to_string(), value.clone()));
+				}
+				// The body is compiled once here, rather than each time the lambda is called
+				names.extend(captures.iter().map(|(var, _)| var.clone()));
+				let body = TextMacro::with_params(names, parsing::unescape(body));
+				Ok(Call::Replace(state.handle(Handle::Lambda { captures, body })))
+			}
+			// The target is called in place of this call, passing the policy and interceptors on its own
+			CoreMacro::Call => {
+				let [target, ref arguments @ ..] = arguments[..] else { return Err(not_enough(1)) };
+				let target = parsing::MacroRange { range: macro_range.range.clone(), name: target, arguments: arguments.to_vec() };
+				self.call_in_place(state, tracer, node, name, &target)
+			}
+			CoreMacro::Apply => {
+				let [target, list, ..] = arguments[..] else { return Err(not_enough(2)) };
+				let list = parsing::unescape(list);
+				let arguments = if list.is_empty() { Vec::new() } else { parsing::split_list(&list) };
+				let target = parsing::MacroRange { range: macro_range.range.clone(), name: target, arguments };
+				self.call_in_place(state, tracer, node, name, &target)
+			}
+			CoreMacro::Partial => {
+				let [target, ref arguments @ ..] = arguments[..] else { return Err(not_enough(1)) };
+				Ok(Call::Replace(state.handle(Handle::Partial {
+					target: target.to_string(),
+					arguments: arguments.iter().map(ToString::to_string).collect()
+				})))
+			}
+			CoreMacro::Compose => {
+				let [_, _, ..] = arguments[..] else { return Err(not_enough(2)) };
+				Ok(Call::Replace(state.handle(Handle::Compose {
+					targets: arguments.iter().map(ToString::to_string).collect()
+				})))
+			}
+		}
+	}
+
+	/// Calls a macro in place of the call with the given name that was traced as the given node,
+	/// so recursion through it is noticed.
+	///
+	/// Each call made this way counts towards the same nesting limit as [`Context::evaluate`], since it takes up native stack.
+	fn call_in_place(
+		&self, state: &mut State, tracer: &mut Option<Tracer>, node: Option<usize>, name: &str, target: &parsing::MacroRange<'_>
+	) -> Result<Call, MacroError> {
+		if state.nesting >= MAX_NESTING {
+			return Err(too_deep(name));
+		}
+		if let (Some(tracer), Some(node)) = (tracer.as_mut(), node) {
+			tracer.dispatch(node, target.name);
+		}
+		self.check_recursion(tracer.as_ref(), node, target.name)?;
+		state.nesting += 1;
+		let result = self.call(state, tracer, node, target);
+		state.nesting -= 1;
+		result
+	}
+
+	/// Fails a traced call that's nested in itself more than the recursion limit allows.
+	fn check_recursion(&self, tracer: Option<&Tracer>, node: Option<usize>, name: &str) -> Result<(), MacroError> {
+		let (Some(tracer), Some(node), Some(limit)) = (tracer, node, self.recursion_limit) else { return Ok(()) };
+		match tracer.cycle(node, limit) {
+			Some(cycle) => Err(MacroError::new(name.into(), MacroErrorKind::RecursionLimit { limit, cycle })),
+			None => Ok(())
+		}
+	}
+
+	/// Unwinds the try stack until a frame catches the error, replacing its call with the result.
+	/// Returns the range of the call, and the result.
+	///
+	/// # Errors
+	/// Errors if no frame catches the error, leaving only the root frame.
+	fn unwind(
+		&self, frames: &mut Vec<Frame>, state: &mut State, mut err: MacroError
+	) -> Result<(Range<usize>, String), Escaped> {
+		let mut escaped = None;
+		while let Some(frame) = frames.pop() {
+			let (result, caught) = match &frame.kind {
+				FrameKind::Root => {
+					frames.push(frame);
+					break;
+				}
+				kind if !kind.catches(&err.error_type) => (Err(err), None),
+				FrameKind::Try { .. } => (
+					Ok(escape(&format!("false/{}", self.catalog.message(&err.error_type)), false)),
+					Some(err)
+				),
+				// The error is inserted as-is, since it may contain strings the handler would replace
+				FrameKind::Catch { handler, .. } => (
+					Ok(TextMacro::new(handler.clone()).substitute(&[
+						&escape(&err.name, true),
+						err.error_type.identifier(),
+						&escape(&self.catalog.message(&err.error_type), true)
+					])),
+					Some(err)
+				)
+			};
+			let arguments: Vec<&str> = frame.arguments.iter().map(String::as_str).collect();
+			match self.after(&frame.name, &arguments, result) {
+				Ok(result) => {
+					let parent = frames.last_mut().expect("only the root frame has no parent");
+					parent.input.replace_range(frame.slot.clone(), &result);
+					if caught.is_some() {
+						state.caught = caught;
+					}
+					return Ok((frame.slot, result));
+				}
+				Err(next) => {
+					err = next;
+					escaped = Some(frame.slot);
+				}
+			}
+		}
+		Err((err, escaped))
+	}
+}
+
+/// An error that unwinding didn't catch, and the range of the call in the root frame it escaped from, if any.
+type Escaped = (MacroError, Option<Range<usize>>);
+
+/// Escapes backslashes and brackets in a string, and optionally slashes.
+fn escape(string: &str, slashes: bool) -> String {
+	let escaped = string
+		.replace('\\', r"\\")
+		.replace('[', r"\[")
+		.replace(']', r"\]");
+	if slashes { escaped.replace('/', r"\/") } else { escaped }
+}
+
+/// The state of an evaluation that failed, returned by [`Engine::apply_with_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+	/// The error that stopped the evaluation.
+	pub error: MacroError,
+	/// The partially evaluated input at the time of the error.
+	///
+	/// Calls to `try` and `catch` that were being evaluated are still unevaluated in this string.
+	pub input: String,
+	/// The variables that were stored at the time of the error.
+	pub variables: HashMap<String, String>,
+	/// The amount of macro calls made before and including the one that failed.
+	pub steps: usize
 }
 
-macro_rules! throw_error {
-	($label: tt, $try_stack: ident, $expr: expr) => {
-		let err = $expr;
-		if let Some((parent, par_range)) = $try_stack.last_mut() {
-			let replace = &format!("false/{}", err.error_type)
-					.replace("\\", r"\\")
-					.replace("[", r"\[")
-					.replace("]", r"\]");
-			parent.replace_range(par_range.clone(), replace);
-			continue $label;
-		}
-		return Err($expr);
-	};
-	((dne) $label: tt, $try_stack: ident, $name: expr) => {
-		throw_error!($label, $try_stack, MacroError {
-        	name: $name.into(), 
-        	error_type: MacroErrorKind::Nonexistent
-       	})
-	};
-	((not_enough) $label: tt, $try_stack: ident, $name: literal, $expected: literal, $found: literal) => {
-		throw_error!($label, $try_stack, MacroError {
-        	name: $name.into(), 
-        	error_type: MacroErrorKind::NotEnoughArguments {
-        		expected: $expected,
-        		found: $found
-       		}
-       	})
-	};
-	((user) $label: tt, $try_stack: ident, $name: literal, $message: literal; $($tt: tt)*) => {
-		throw_error!($label, $try_stack, MacroError {
-        	name: $name.into(), 
-        	error_type: MacroErrorKind::User {
-        		message: format!($message, $($tt)*)
-       		}
-       	})
+impl std::error::Error for Failure {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (after {} steps)", self.error, self.steps)
+    }
+}
+
+/// The result of [`Engine::apply_recovering`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Recovered {
+	/// The output, with every failed call replaced by a marker.
+	pub output: String,
+	/// Every error that was recovered from, in the order they occurred.
+	pub errors: Vec<RecoveredError>
+}
+
+/// An error that was recovered from by [`Engine::apply_recovering`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecoveredError {
+	/// The error that occurred.
+	pub error: MacroError,
+	/// The byte range of the failed call in the string it was found in, at the time it failed.
+	///
+	/// This is the string being rewritten at that point, which may be the inside of a `try` or `catch`,
+	/// and may have been changed by later calls.
+	pub span: Range<usize>
+}
+
+/**
+An evaluation of a string that advances one step at a time, created by [`Engine::evaluate`].
+
+Each step either calls one macro, or finishes evaluating the inside of a `try` or `catch`.
+This allows pausing, single-stepping, and interleaving many evaluations on one thread.
+
+## Example
+```
+#    use macroscript::{Engine, Progress, add_stdlib};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros);
+let mut evaluation = engine.evaluate("[add/1/[multiply/2/3]]".into());
+assert_eq!("multiply", evaluation.pending().unwrap().name);
+assert_eq!(Progress::Running, evaluation.step()?);
+assert_eq!("[add/1/6]", evaluation.current());
+assert_eq!(Progress::Running, evaluation.step()?);
+assert_eq!(Progress::Finished("7".into()), evaluation.step()?);
+assert_eq!(2, evaluation.steps());
+#        Ok(()) }
+```
+
+## Suspending
+A [suspendable](Evaluation::suspendable) evaluation pauses at calls to the `input` core macro,
+until the host resumes it with a value that replaces the call.
+A paused evaluation can be turned into a [`Continuation`], which doesn't borrow the engine,
+and with the `serde` feature, can be serialized to survive a restart.
+
+Other names can be made to suspend with [`CoreMacros::with`].
+
+```
+#    use macroscript::{Engine, Progress, Suspension, add_stdlib};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros);
+let mut evaluation = engine.evaluate("[store/x/1]Hello, [upper/[input/name?]]!".into()).suspendable(true);
+let Progress::Suspended(suspension) = evaluation.run()? else { panic!() };
+assert_eq!(Suspension { name: "input".into(), arguments: vec!["name?".into()] }, suspension);
+let continuation = evaluation.into_continuation();
+// ...later...
+let mut evaluation = engine.resume(continuation);
+evaluation.resume("world");
+assert_eq!(Progress::Finished("Hello, WORLD!".into()), evaluation.run()?);
+assert_eq!(Some("1"), evaluation.variables().get("x").map(String::as_str));
+#        Ok(()) }
+```
+*/
+pub struct Evaluation<'engine, 'macros, S = std::collections::hash_map::RandomState> {
+	engine: &'engine Engine<'macros, S>,
+	frames: Vec<Frame>,
+	state: State,
+	recovering: bool,
+	recovered: Vec<RecoveredError>,
+	suspendable: bool,
+	suspended: Option<(Range<usize>, Suspension)>,
+	profiler: Option<Profiler>,
+	tracer: Option<Tracer>
+}
+
+/// The state of a paused [`Evaluation`], which doesn't borrow the engine.
+///
+/// With the `serde` feature, this can be serialized and deserialized.
+///
+/// ## Example
+/// ```
+/// #    use macroscript::{Engine, Progress, Continuation, add_stdlib};
+/// #    use std::collections::HashMap;
+/// #
+/// # #[cfg(feature = "serde")]
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut macros = HashMap::new();
+/// add_stdlib(&mut macros);
+/// let engine = Engine::new(&macros);
+/// let mut evaluation = engine.evaluate(r"[store/f/[lambda/x/$x!]][try/\[add\/1\/\[input\]\]] [[load/f]/hi]".into()).suspendable(true);
+/// evaluation.run()?;
+/// let saved = serde_json::to_string(&evaluation.into_continuation())?;
+/// let continuation: Continuation = serde_json::from_str(&saved)?;
+/// let mut evaluation = engine.resume(continuation);
+/// evaluation.resume("2");
+/// assert_eq!(Progress::Finished("true/3 hi!".into()), evaluation.run()?);
+/// #        Ok(()) }
+/// # #[cfg(not(feature = "serde"))]
+/// # fn main() {}
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Continuation {
+	frames: Vec<Frame>,
+	state: State,
+	recovering: bool,
+	recovered: Vec<RecoveredError>,
+	suspendable: bool,
+	suspended: Option<(Range<usize>, Suspension)>
+}
+
+impl Continuation {
+	/// The macro call the evaluation is suspended at, if any.
+	#[must_use]
+	pub fn suspension(&self) -> Option<&Suspension> {
+		self.suspended.as_ref().map(|(_, suspension)| suspension)
+	}
+
+	/// The try stack, from the root input to the innermost `try` or `catch`.
+	#[must_use]
+	pub fn frames(&self) -> &[Frame] {
+		&self.frames
+	}
+
+	/// The variables currently stored.
+	#[must_use]
+	pub fn variables(&self) -> &HashMap<String, String> {
+		&self.state.variables
+	}
+}
+
+/// A macro call that suspended an [`Evaluation`] to ask the host for input.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Suspension {
+	/// The name the macro was called with.
+	pub name: String,
+	/// The arguments of the call, such as a prompt.
+	pub arguments: Vec<String>
+}
+
+/// The result of a single step of an [`Evaluation`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Progress {
+	/// There are still macros left to apply.
+	Running,
+	/// There are no macros left to apply, and this is the output.
+	Finished(String),
+	/// The evaluation is waiting for the host to [resume](Evaluation::resume) it.
+	Suspended(Suspension)
+}
+
+impl<S: std::hash::BuildHasher> Evaluation<'_, '_, S> {
+	/// Sets whether uncaught errors are recovered from, like [`Engine::apply_recovering`].
+	#[must_use]
+	pub fn recovering(mut self, recovering: bool) -> Self {
+		self.recovering = recovering;
+		self
+	}
+
+	/// Sets whether the evaluation can be suspended by `input`.
+	///
+	/// If it can't, `input` raises [`MacroErrorKind::CannotSuspend`].
+	#[must_use]
+	pub fn suspendable(mut self, suspendable: bool) -> Self {
+		self.suspendable = suspendable;
+		self
+	}
+
+	/// Sets whether the time spent in each macro is recorded. See [`Profile`].
+	///
+	/// Turning profiling on discards any profile recorded so far.
+	#[must_use]
+	pub fn profiling(mut self, profiling: bool) -> Self {
+		self.profiler = profiling.then(Profiler::default);
+		self
+	}
+
+	/// Sets whether the calls made are recorded as an [`ExpansionTree`].
+	///
+	/// Turning tracing on discards any tree recorded so far.
+	#[must_use]
+	pub fn tracing(mut self, tracing: bool) -> Self {
+		let tracked = tracing || self.engine.recursion_limit.is_some();
+		self.tracer = tracked.then(|| Tracer::new(self.frames.len(), tracing));
+		self
+	}
+
+	/// Resumes a suspended evaluation, replacing the call that suspended it with a value.
+	///
+	/// Backslashes and brackets in the value are escaped, so it can't call macros.
+	/// Does nothing if the evaluation isn't suspended.
+	pub fn resume(&mut self, value: &str) {
+		let Some((range, _)) = self.suspended.take() else { return };
+		let Some(frame) = self.frames.last_mut() else { unreachable!("the root frame is never popped") };
+		let value = escape(value, false);
+		if let Some(tracer) = &mut self.tracer {
+			if let Some(node) = tracer.suspended.take() {
+				tracer.finish(node, Ok(&value));
+				tracer.replace(&range, value.len(), node);
+			}
+		}
+		frame.input.replace_range(range, &value);
+	}
+
+	/// Pauses the evaluation, so it can be stored without borrowing the engine.
+	///
+	/// It can be continued later with [`Engine::resume`].
+	/// The [profile](Evaluation::profile) and [expansion tree](Evaluation::expansion_tree) aren't kept.
+	#[must_use]
+	pub fn into_continuation(self) -> Continuation {
+		Continuation {
+			frames: self.frames,
+			state: self.state,
+			recovering: self.recovering,
+			recovered: self.recovered,
+			suspendable: self.suspendable,
+			suspended: self.suspended
+		}
+	}
+
+	/// Advances the evaluation by one step.
+	///
+	/// Once finished, further steps return the output again, and while suspended, further steps do nothing.
+	/// If a step fails, the evaluation is left as it was before the failed call,
+	/// so stepping again retries the call.
+	///
+	/// # Errors
+	/// Errors if a macro errors, and the error isn't caught or recovered from.
+	pub fn step(&mut self) -> Result<Progress, Box<Failure>> {
+		if let Some((_, suspension)) = &self.suspended {
+			return Ok(Progress::Suspended(suspension.clone()));
+		}
+		let is_root = self.frames.len() == 1;
+		let Some(frame) = self.frames.last_mut() else { unreachable!("the root frame is never popped") };
+		if let Some(profiler) = &mut self.profiler {
+			profiler.profile.rescans += 1;
+		}
+		let Some(macro_range) = parsing::find_pair(&frame.input) else {
+			if is_root {
+				if !self.state.warned {
+					self.state.warned = true;
+					for position in parsing::find_brackets(&frame.input) {
+						self.engine.emit(&mut self.state, Output::Warning(Warning::UnmatchedBracket { position }));
+					}
+				}
+				return Ok(Progress::Finished(frame.input.clone()));
+			}
+			let Some(mut frame) = self.frames.pop() else { unreachable!("a frame was just found") };
+			let node = self.tracer.as_mut().and_then(Tracer::pop);
+			let input = std::mem::take(&mut frame.input);
+			let result = match frame.kind {
+				FrameKind::Try { .. } => format!("true/{input}"),
+				FrameKind::Root | FrameKind::Catch { .. } => input
+			};
+			let result = self.engine.after_frame(&frame, result);
+			if let Some(profiler) = &mut self.profiler {
+				profiler.close(&frame.name, result.as_ref().ok().map(String::len));
+			}
+			return match result {
+				Ok(result) => {
+					if let (Some(tracer), Some(node)) = (&mut self.tracer, node) {
+						tracer.finish(node, Ok(&result));
+						tracer.replace(&frame.slot, result.len(), node);
+					}
+					let Some(parent) = self.frames.last_mut() else { unreachable!("only the root frame has no parent") };
+					parent.input.replace_range(frame.slot, &result);
+					Ok(Progress::Running)
+				}
+				Err(err) => self.fail(frame.slot, err, node)
+			};
+		};
+		let range = macro_range.range.clone();
+		let node = self.tracer.as_mut().map(|tracer| tracer.call(&range, macro_range.name, &macro_range.arguments));
+		let start = Instant::now();
+		let result = self.engine.check_recursion(self.tracer.as_ref(), node, macro_range.name)
+			.and_then(|()| self.engine.call(&mut self.state, &mut self.tracer, node, &macro_range));
+		if let Some(profiler) = &mut self.profiler {
+			let name = macro_range.name;
+			match &result {
+				Ok(Call::Replace(value)) => profiler.record(name, start.elapsed(), Some(value.len())),
+				Ok(Call::Push(_)) => profiler.open(name, start),
+				Ok(Call::Suspend(_)) | Err(_) => profiler.record(name, start.elapsed(), None)
+			}
+		}
+		let err = match result {
+			Ok(Call::Replace(value)) => {
+				if let (Some(tracer), Some(node)) = (&mut self.tracer, node) {
+					tracer.finish(node, Ok(&value));
+					tracer.replace(&range, value.len(), node);
+				}
+				frame.input.replace_range(range, &value);
+				return Ok(Progress::Running);
+			}
+			Ok(Call::Push(frame)) => {
+				if let (Some(tracer), Some(node)) = (&mut self.tracer, node) {
+					tracer.push(node);
+				}
+				self.frames.push(frame);
+				return Ok(Progress::Running);
+			}
+			Ok(Call::Suspend(suspension)) if self.suspendable => {
+				if let Some(tracer) = &mut self.tracer {
+					tracer.suspended = node;
+				}
+				self.suspended = Some((range, suspension.clone()));
+				return Ok(Progress::Suspended(suspension));
+			}
+			Ok(Call::Suspend(suspension)) => MacroError::new(suspension.name, MacroErrorKind::CannotSuspend),
+			Err(err) => err
+		};
+		self.fail(range, err, node)
+	}
+
+	/// Handles an error raised by the call at the given range of the innermost frame, which was traced as the given node.
+	fn fail(&mut self, range: Range<usize>, err: MacroError, node: Option<usize>) -> Result<Progress, Box<Failure>> {
+		if let (Some(tracer), Some(node)) = (&mut self.tracer, node) {
+			tracer.finish(node, Err(&err));
+		}
+		let caught = self.frames.iter().any(|frame| frame.kind.catches(&err.error_type));
+		if !caught && self.recovering {
+			self.recover(range, err, node);
+			return Ok(Progress::Running);
+		}
+		// Only unwind if something catches the error, so an uncaught error leaves the try stack as-is
+		let result = if caught {
+			let names: Vec<String> = match self.profiler {
+				Some(_) => self.frames.iter().map(|frame| frame.name.clone()).collect(),
+				None => Vec::new()
+			};
+			let depth = self.frames.len();
+			let result = self.engine.unwind(&mut self.frames, &mut self.state, err);
+			if let Some(profiler) = &mut self.profiler {
+				for name in names[self.frames.len() ..].iter().rev() {
+					profiler.close(name, None);
+				}
+			}
+			let popped = match &mut self.tracer {
+				Some(tracer) => (self.frames.len() .. depth).filter_map(|_| tracer.pop()).collect(),
+				None => Vec::new()
+			};
+			// The last frame popped is the one that caught the error
+			if let (Some(tracer), Ok((slot, result)), Some(&node)) = (&mut self.tracer, &result, popped.last()) {
+				tracer.finish(node, Ok(result));
+				tracer.replace(slot, result.len(), node);
+			}
+			match result {
+				Ok(_) => Ok(()),
+				// An interceptor turned the result of the frame into an error that nothing else caught
+				Err((err, Some(slot))) if self.recovering => {
+					let node = popped.last().copied();
+					if let (Some(tracer), Some(node)) = (&mut self.tracer, node) {
+						tracer.finish(node, Err(&err));
+					}
+					self.recover(slot, err, node);
+					Ok(())
+				}
+				Err((err, _)) => Err(err)
+			}
+		} else { Err(err) };
+		result.map(|()| Progress::Running).map_err(|error| Box::new(Failure {
+			error,
+			input: self.frames[0].input.clone(),
+			variables: self.state.variables.clone(),
+			steps: self.state.steps
+		}))
+	}
+
+	/// Replaces a failed call in the innermost frame with a marker, and records the error.
+	fn recover(&mut self, range: Range<usize>, err: MacroError, node: Option<usize>) {
+		let marker = self.engine.marker(&err);
+		if let (Some(tracer), Some(node)) = (&mut self.tracer, node) {
+			tracer.replace(&range, marker.len(), node);
+		}
+		let Some(frame) = self.frames.last_mut() else { unreachable!("the failed call is in a frame") };
+		frame.input.replace_range(range.clone(), &marker);
+		self.recovered.push(RecoveredError { error: err, span: range });
+	}
+
+	/// Steps the evaluation until it finishes or is suspended.
+	///
+	/// # Errors
+	/// Errors if a macro errors, and the error isn't caught or recovered from.
+	pub fn run(&mut self) -> Result<Progress, Box<Failure>> {
+		loop {
+			let progress = self.step()?;
+			if progress != Progress::Running {
+				return Ok(progress);
+			}
+		}
+	}
+
+	/// The string currently being rewritten, which is the inside of the innermost `try` or `catch`, if any.
+	#[must_use]
+	pub fn current(&self) -> &str {
+		&self.frames[self.frames.len() - 1].input
+	}
+
+	/// The macro call the next step will make, if there is one.
+	#[must_use]
+	pub fn pending(&self) -> Option<MacroRange<'_>> {
+		parsing::find_pair(self.current())
+	}
+
+	/// The try stack, from the root input to the innermost `try` or `catch`.
+	#[must_use]
+	pub fn frames(&self) -> &[Frame] {
+		&self.frames
+	}
+
+	/// The variables currently stored.
+	#[must_use]
+	pub fn variables(&self) -> &HashMap<String, String> {
+		&self.state.variables
+	}
+
+	/// The amount of macro calls made so far.
+	#[must_use]
+	pub fn steps(&self) -> usize {
+		self.state.steps
+	}
+
+	/// The errors recovered from so far, if recovering.
+	#[must_use]
+	pub fn recovered(&self) -> &[RecoveredError] {
+		&self.recovered
+	}
+
+	/// The expansion tree recorded so far, if [tracing](Evaluation::tracing).
+	#[must_use]
+	pub fn expansion_tree(&self) -> Option<&ExpansionTree> {
+		self.tracer.as_ref().and_then(|tracer| tracer.tree.as_ref())
+	}
+
+	/// The profile recorded so far, if [profiling](Evaluation::profiling).
+	#[must_use]
+	pub fn profile(&self) -> Option<&Profile> {
+		self.profiler.as_ref().map(|profiler| &profiler.profile)
+	}
+
+	/// The side-channel output collected so far, if the engine has no [sink](Engine::with_sink).
+	///
+	/// ## Example
+	/// ```
+	/// #    use macroscript::{Engine, Progress, add_stdlib, output::{Output, LogLevel}};
+	/// #    use std::collections::HashMap;
+	/// #
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// let mut macros = HashMap::new();
+	/// add_stdlib(&mut macros);
+	/// let engine = Engine::new(&macros);
+	/// let mut evaluation = engine.evaluate("[print/a/b][log_at/debug/[add/1/2]]done".into());
+	/// assert_eq!(Progress::Finished("done".into()), evaluation.run()?);
+	/// assert_eq!(&[
+	///     Output::Print("a/b".into()),
+	///     Output::Log { level: LogLevel::Debug, message: "3".into() }
+	/// ], evaluation.outputs());
+	/// #        Ok(()) }
+	/// ```
+	#[must_use]
+	pub fn outputs(&self) -> &[Output] {
+		&self.state.outputs
+	}
+
+	/// Takes the side-channel output collected so far, leaving none behind.
+	pub fn take_outputs(&mut self) -> Vec<Output> {
+		std::mem::take(&mut self.state.outputs)
+	}
+}
+
+/// What happens to the result of a [`Frame`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[allow(missing_docs)]
+pub enum FrameKind {
+	/// The input of the evaluation, whose result is the final output.
+	Root,
+	/// A `try` call, whose result is prefixed with whether it succeeded. Only errors of the given kinds are caught, if any.
+	Try { kinds: Vec<String> },
+	/// A `catch` call, whose errors are passed to a handler. Only errors of the given kinds are caught, if any.
+	Catch { handler: String, kinds: Vec<String> }
+}
+
+impl FrameKind {
+	/// Returns whether this frame catches an error.
+	/// Frames catch every kind of error unless specific kinds are listed.
+	#[must_use]
+	pub fn catches(&self, error: &MacroErrorKind) -> bool {
+		match self {
+			FrameKind::Root => false,
+			FrameKind::Try { kinds } | FrameKind::Catch { kinds, .. } =>
+				kinds.is_empty() || kinds.iter().any(|kind| kind == error.identifier())
+		}
+	}
+}
+
+/// A script on the try stack, along with where its result goes in its parent.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Frame {
+	input: String,
+	slot: Range<usize>,
+	name: String,
+	arguments: Vec<String>,
+	kind: FrameKind
+}
+
+impl Frame {
+	/// The script, as rewritten so far.
+	#[must_use]
+	pub fn input(&self) -> &str {
+		&self.input
+	}
+
+	/// The byte range of the call in the parent frame that this frame's result replaces.
+	/// This is empty for the root frame.
+	#[must_use]
+	pub fn slot(&self) -> Range<usize> {
+		self.slot.clone()
+	}
+
+	/// The name of the call that created this frame. This is empty for the root frame.
+	#[must_use]
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// The arguments of the call that created this frame, still escaped.
+	#[must_use]
+	pub fn arguments(&self) -> &[String] {
+		&self.arguments
+	}
+
+	/// What happens to this frame's result.
+	#[must_use]
+	pub fn kind(&self) -> &FrameKind {
+		&self.kind
+	}
+}
+
+/// The outcome of a single macro call.
+enum Call {
+	/// The call is replaced with a string.
+	Replace(String),
+	/// The call is replaced with the result of a new frame.
+	Push(Frame),
+	/// The call is replaced with a value supplied by the host later.
+	Suspend(Suspension)
+}
+
+/// State shared by all frames of an evaluation.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct State {
+	variables: HashMap<String, String>,
+	steps: usize,
+	calls: HashMap<String, usize>,
+	caught: Option<MacroError>,
+	outputs: Vec<Output>,
+	warned: bool,
+	handles: Vec<Handle>,
+	/// How many evaluations and calls through `call` and `apply` the current call is nested in,
+	/// which is only nonzero while one of them is running.
+	nesting: usize
+}
+
+impl State {
+	/// Stores an anonymous macro, returning its handle.
+	fn handle(&mut self, handle: Handle) -> String {
+		self.handles.push(handle);
+		format!("{HANDLE_PREFIX}{}", self.handles.len() - 1)
+	}
+}
+
+/// How many evaluations may be nested inside each other through [`Context::evaluate`], `call` and `apply`.
+const MAX_NESTING: usize = 64;
+
+/// Creates the error for nesting more deeply than [`MAX_NESTING`].
+fn too_deep(name: &str) -> MacroError {
+	MacroError::new(name.into(), MacroErrorKind::LimitExceeded { limit: Limit::Nesting, value: MAX_NESTING })
+}
+
+/// The [`Context`] given to macros in the registry, which evaluates strings inside the calling evaluation.
+struct Nested<'engine, 'state, 'macros, S> {
+	engine: &'engine Engine<'macros, S>,
+	state: &'state mut State,
+	tracer: &'state mut Option<Tracer>,
+	/// The call being made, as traced.
+	node: Option<usize>
+}
+
+impl<S: std::hash::BuildHasher> Context for Nested<'_, '_, '_, S> {
+	fn evaluate(&mut self, input: &str) -> Result<String, MacroError> {
+		if self.state.nesting >= MAX_NESTING {
+			// The engine fills in the name of the calling macro
+			return Err(too_deep(""));
+		}
+		let mut tracer = self.tracer.take();
+		let depth = tracer.as_ref().map(Tracer::depth);
+		if let (Some(tracer), Some(node)) = (&mut tracer, self.node) {
+			tracer.push(node);
+		}
+		// Stray brackets are only warned about once the outermost evaluation finishes
+		let warned = std::mem::replace(&mut self.state.warned, true);
+		let mut evaluation = Evaluation {
+			engine: self.engine,
+			frames: vec![Frame { input: input.to_string(), slot: 0..0, name: String::new(), arguments: Vec::new(), kind: FrameKind::Root }],
+			state: std::mem::take(self.state),
+			recovering: false,
+			recovered: Vec::new(),
+			suspendable: false,
+			suspended: None,
+			profiler: None,
+			tracer
+		};
+		evaluation.state.nesting += 1;
+		let result = evaluation.run();
+		*self.state = evaluation.state;
+		self.state.nesting -= 1;
+		self.state.warned = warned;
+		*self.tracer = evaluation.tracer;
+		// A failed evaluation is left with the frames it was in
+		if let (Some(tracer), Some(depth)) = (&mut *self.tracer, depth) {
+			tracer.truncate(depth);
+		}
+		match result {
+			Ok(Progress::Finished(output)) => Ok(output),
+			Ok(_) => unreachable!("the evaluation isn't suspendable"),
+			Err(failure) => Err(failure.error)
+		}
+	}
+
+	fn number_mode(&self) -> NumberMode {
+		self.engine.number_mode
+	}
+}
+
+/// Creates the error for loading a variable that doesn't exist.
+fn undefined_variable(name: &str, var: &str, state: &State) -> MacroError {
+	MacroError::new(name.into(), MacroErrorKind::UndefinedVariable {
+		name: var.to_string(),
+		suggestions: suggestions(var, state.variables.keys().map(String::as_str))
+	})
+}
+
+/// The character handles to anonymous macros start with, followed by their index.
+const HANDLE_PREFIX: char = 'λ';
+
+/// Finds which anonymous macro a name is a handle to, if it's a handle.
+pub(crate) fn handle_id(name: &str) -> Option<usize> {
+	let id = name.strip_prefix(HANDLE_PREFIX)?;
+	// Rule out signs and leading zeroes, so each anonymous macro has one handle
+	if id.starts_with(['+', '-']) || (id.len() > 1 && id.starts_with('0')) {
+		return None;
+	}
+	id.parse().ok()
+}
+
+/// An anonymous macro created by `lambda`, `partial` or `compose`,
+/// which lives until the end of the evaluation that created it.
+///
+/// Calling one replaces the call with macroscript, which is evaluated in its place.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Handle {
+	Lambda {
+		/// The names and values of the captured variables.
+		captures: Vec<(String, String)>,
+		/// The body, whose parameters are the lambda's followed by the names of the captured variables.
+		#[cfg_attr(feature = "serde", serde(with = "compiled"))]
+		body: TextMacro
+	},
+	Partial {
+		target: String,
+		/// The arguments passed before the ones the handle is called with.
+		arguments: Vec<String>
+	},
+	Compose {
+		/// The macros to call, where each one is called with the result of the next.
+		targets: Vec<String>
+	}
+}
+
+impl Macro for Handle {
+	fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+		match self {
+			Handle::Lambda { captures, body } => {
+				let params = &body.params()[.. body.params().len() - captures.len()];
+				let (expected, found) = (params.len(), arguments.len());
+				if found > expected {
+					return Err(MacroError::new(String::new(), MacroErrorKind::TooManyArguments { expected, found }));
+				}
+				if let Some(missing) = params.get(found) {
+					return Err(MacroError::new(String::new(), MacroErrorKind::missing_parameter(expected, found, missing)));
+				}
+				// Captured values are passed after the arguments, so they can be named like parameters
+				let mut arguments = arguments;
+				arguments.extend(captures.iter().map(|(_, value)| value.as_str()));
+				body.apply(arguments)
+			}
+			Handle::Partial { target, arguments: fixed } => Ok(format!(
+				"[{}]", std::iter::once(target.as_str()).chain(fixed.iter().map(String::as_str)).chain(arguments).join("/")
+			)),
+			Handle::Compose { targets } => {
+				let Some((innermost, outer)) = targets.split_last() else { unreachable!("compose takes at least two macros") };
+				let mut call = format!("[{}]", std::iter::once(innermost.as_str()).chain(arguments).join("/"));
+				for target in outer.iter().rev() {
+					call = format!("[{target}/{call}]");
+				}
+				Ok(call)
+			}
+		}
+	}
+}
+
+/// (De)serializes the compiled body of a lambda as its parameters and pattern, compiling it again when deserialized.
+#[cfg(feature = "serde")]
+mod compiled {
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+	use crate::TextMacro;
+
+	pub(super) fn serialize<S: Serializer>(mac: &TextMacro, serializer: S) -> Result<S::Ok, S::Error> {
+		(mac.params(), mac.pattern()).serialize(serializer)
+	}
+
+	pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TextMacro, D::Error> {
+		let (params, pattern) = <(Vec<String>, String)>::deserialize(deserializer)?;
+		Ok(TextMacro::with_params(params, pattern))
 	}
 }
 
 /// Applies all found macros in the string until none are left.
 ///
+/// This uses the default core macro configuration. For more control, see [`Engine`].
+///
 /// # Errors
 /// Errors if any macro in the input errors.
 pub fn apply_macros(
     input: String,
     macros: &HashMap<String, Box<dyn Macro>, impl std::hash::BuildHasher>,
 ) -> Result<String, MacroError> {
-    let input_len = input.len();
-    let mut variables: HashMap<String, String> = HashMap::new();
-    let mut try_stack = vec![(input, 0..input_len)];
-    'try_loop: while let Some((mut input, range)) = try_stack.pop() { // pop isn't optimal here, but would take a huge refactor
-        while let Some(macro_range) = parsing::find_pair(&input) {
-            match macro_range.name {
-                "try" => {
-                    let mac_range = macro_range.range;
-                    let Some(new_input) = macro_range.arguments.first() else {
-                        throw_error!((not_enough) 
-                        	'try_loop, try_stack, "try",
-							1, 0
-                       	);
-                    };
-                    let new_input = parsing::unescape(new_input).into_owned();
-                    try_stack.push((input, range));
-                    try_stack.push((new_input, mac_range));
-                    continue 'try_loop;
-                }
-                "load" => {
-                    let Some(name) = macro_range.arguments.first() else {
-                        throw_error!((not_enough) 
-                        	'try_loop, try_stack, "load",
-							1, 0
-                       	);
-                    };
-                    let range = macro_range.range;
-                    let Some(value) = variables.get(*name) else {
-                        throw_error!((user)
-                        	'try_loop, try_stack, "load",
-                        	"variable \"{}\" does not currently exist";
-                       		name
-                       	);
-                    };
-                    input.replace_range(range, value);
-                }
-                "drop" => {
-                    let Some(name) = macro_range.arguments.first() else {
-                        throw_error!((not_enough) 
-                        	'try_loop, try_stack, "drop",
-							1, 0
-                      	);
-                    };
-                    let range = macro_range.range;
-                    variables.remove(*name);
-                    input.replace_range(range, "");
-                }
-                "store" => {
-                    let Some(name) = macro_range.arguments.first() else {
-						throw_error!((not_enough) 
-                        	'try_loop, try_stack, "store",
-							2, 0
-                      	);
-                    };
-                    let Some(value) = macro_range.arguments.get(1) else {
-						throw_error!((not_enough) 
-                        	'try_loop, try_stack, "store",
-							2, 1
-                      	);
-                    };
-                    let range = macro_range.range;
-                    variables.insert((*name).to_string(), (*value).to_string());
-                    input.replace_range(range, "");
-                }
-                "get" => {
-                    let Some(name) = macro_range.arguments.first() else {
-						throw_error!((not_enough) 
-                        	'try_loop, try_stack, "get",
-                        	2, 0
-                      	);
-                    };
-                    let Some(value) = macro_range.arguments.get(1) else {
-						throw_error!((not_enough) 
-                        	'try_loop, try_stack, "get",
-							2, 1
-                      	);
-                    };
-                    let range = macro_range.range;
-                    let result = variables
-                        .entry((*name).to_string())
-                        .or_insert((*value).to_string());
-                    input.replace_range(range, result);
-                }
-                "is_stored" => {
-                    let Some(name) = macro_range.arguments.first() else {
-						throw_error!((not_enough) 
-                        	'try_loop, try_stack, "is_stored",
-							1, 0
-                      	);
-                   	};
-                    let range = macro_range.range;
-                    let exists = variables.contains_key(*name);
-                    input.replace_range(range, &exists.to_string());
-                }
-                other => {
-                    let range = macro_range.range;
-                    let Some(mac) = macros.get(other) else {
-						throw_error!((dne) 'try_loop, try_stack, other);
-                    };
-                    let replace = match mac.apply(macro_range.arguments) {
-                    	Ok(value) => value,
-                    	Err(err) => {throw_error!('try_loop, try_stack, err.clone());}
-                    }; 
-                    input.replace_range(range, &replace);
-                }
-            }
-        }
-        if let Some((parent, par_range)) = try_stack.last_mut() {
-            parent.replace_range(par_range.clone(), &format!("true/{input}"));
-        } else {
-            return Ok(input);
-        }
-    }
-    unreachable!()
+    Engine::new(macros).apply(input)
 }
-
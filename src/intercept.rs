@@ -0,0 +1,106 @@
+/*!
+Contains items for observing and changing macro calls as they happen.
+
+If you want to audit, rate-limit or rewrite calls, see the documentation of [`Interceptor`].
+*/
+
+use crate::MacroError;
+
+/**
+Sees every macro call an [`crate::Engine`] dispatches, before and after it runs.
+
+Interceptors see calls to core macros and macros in the registry alike,
+after the call has passed the engine's [policy](crate::policy::Policy).
+Calls to macros that don't exist or aren't permitted are never dispatched, so they're never seen.
+
+Before a call, each interceptor's [`before`](Interceptor::before) runs in the order they were added,
+and the first one that returns a result skips the call, along with the `before` of any later interceptors.
+After a call, each interceptor's [`after`](Interceptor::after) runs in reverse order, even if the call was skipped,
+and can change the result.
+
+For `try` and `catch`, the result is seen once the script inside has been evaluated.
+If an error inside them isn't caught by any enclosing `try` or `catch`, the evaluation stops without their `after` running.
+Values supplied by the host to resume `input` aren't seen.
+
+## Example
+```
+#    use macroscript::{Engine, MacroError, add_stdlib, intercept::Interceptor};
+#    use std::{collections::HashMap, cell::RefCell};
+#
+struct Audit(RefCell<Vec<String>>);
+
+impl Interceptor for Audit {
+    fn after(&self, name: &str, arguments: &[&str], result: Result<String, MacroError>) -> Result<String, MacroError> {
+        let output = result.as_deref().unwrap_or("error");
+        self.0.borrow_mut().push(format!("{name}/{} = {output}", arguments.join("/")));
+        result
+    }
+}
+
+struct FairDice;
+
+impl Interceptor for FairDice {
+    fn before(&self, name: &str, _arguments: &[&str]) -> Option<Result<String, MacroError>> {
+        (name == "rand").then(|| Ok("4".into()))
+    }
+}
+
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let audit = Audit(RefCell::new(Vec::new()));
+let engine = Engine::new(&macros).with_interceptor(&audit).with_interceptor(&FairDice);
+assert_eq!("true/5", engine.apply(r"[try/\[add\/1\/\[rand\]\]]".into())?);
+assert_eq!(vec![
+    "rand/ = 4",
+    "add/1/4 = 5",
+    r"try/\[add\/1\/\[rand\]\] = true/5"
+], audit.0.into_inner());
+#        Ok(()) }
+```
+
+An error returned from `after` is handled like any other error the call raised,
+so an enclosing `try` can catch it, and [`crate::Engine::apply_recovering`] recovers from it.
+
+```
+#    use macroscript::{Engine, MacroError, MacroErrorKind, add_stdlib, intercept::Interceptor};
+#    use std::collections::HashMap;
+#
+struct NoTry;
+
+impl Interceptor for NoTry {
+    fn after(&self, name: &str, _arguments: &[&str], result: Result<String, MacroError>) -> Result<String, MacroError> {
+        if name == "try" { Err(MacroError::new(name.into(), MacroErrorKind::user("try is off"))) } else { result }
+    }
+}
+
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros).with_interceptor(&NoTry);
+let recovered = engine.apply_recovering(r"a[try/\[error\/x\]]b".into());
+assert_eq!("a⟨error in macro try: try is off⟩b", recovered.output);
+assert_eq!(1, recovered.errors.len());
+assert_eq!(1 .. 19, recovered.errors[0].span);
+```
+*/
+pub trait Interceptor {
+	/// Runs before a macro is called.
+	///
+	/// Returning a result skips the call, using the result instead.
+	/// By default, this does nothing.
+	fn before(&self, name: &str, arguments: &[&str]) -> Option<Result<String, MacroError>> {
+		let _ = (name, arguments);
+		None
+	}
+
+	/// Runs after a macro is called, with its result, returning the result to use instead.
+	///
+	/// By default, this returns the result unchanged.
+	///
+	/// # Errors
+	/// Errors if the call should fail.
+	fn after(&self, name: &str, arguments: &[&str], result: Result<String, MacroError>) -> Result<String, MacroError> {
+		let _ = (name, arguments);
+		result
+	}
+}
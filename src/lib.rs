@@ -1,15 +1,20 @@
 #![warn(clippy::pedantic, clippy::perf, missing_docs)]
 #![allow(clippy::too_many_lines)]
-// My editor highlights errors for these, even though I have Rust updated to 1.81.
-#![feature(byte_slice_trim_ascii, lazy_cell)]
 #![doc = include_str!("../README.md")]
 
 pub mod execution;
+pub mod intercept;
+pub mod locale;
+pub mod number;
+pub mod output;
 pub(crate) mod parsing;
+pub mod policy;
+pub mod profile;
 pub mod test;
 pub mod stdlib;
 pub mod textmacro;
+pub mod trace;
 
-pub use execution::{Macro, MacroError, MacroErrorKind, apply_macros};
+pub use execution::{Macro, Context, MacroError, MacroErrorKind, Limit, CoreMacro, CoreMacros, Engine, Evaluation, Continuation, Suspension, Progress, Failure, Recovered, RecoveredError, apply_macros};
 pub use stdlib::add as add_stdlib;
 pub use textmacro::TextMacro;
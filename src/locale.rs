@@ -0,0 +1,71 @@
+/*!
+Contains items for rendering error messages in other languages.
+
+If you want to translate errors, see the documentation of [`MessageCatalog`].
+*/
+
+use crate::{MacroError, MacroErrorKind};
+
+/**
+Renders error messages for a language.
+
+The catalog set on an [`crate::Engine`] is used for the messages `try` returns,
+so scripts see errors in the same language as the host.
+
+Messages of [`MacroErrorKind::User`] come from the script itself, and should usually be passed through as-is.
+
+## Example
+```
+#    use macroscript::{Engine, MacroErrorKind, add_stdlib, locale::{MessageCatalog, English}};
+#    use std::collections::HashMap;
+#
+struct Spanish;
+
+impl MessageCatalog for Spanish {
+    fn message(&self, kind: &MacroErrorKind) -> String {
+        match kind {
+            MacroErrorKind::Nonexistent { .. } => "no encontrado".into(),
+            MacroErrorKind::UndefinedVariable { name, .. } =>
+                format!("la variable \"{name}\" no existe"),
+            other => English.message(other)
+        }
+    }
+
+    fn error(&self, error: &macroscript::MacroError) -> String {
+        format!("error en el macro {}: {}", error.name, self.message(&error.error_type))
+    }
+}
+
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros).with_catalog(&Spanish);
+assert_eq!("false/la variable \"x\" no existe", engine.apply(r"[try/\[load\/x\]]".into())?);
+let err = engine.apply("[nada]".into()).unwrap_err();
+assert_eq!("error en el macro nada: no encontrado", Spanish.error(&err));
+#        Ok(()) }
+```
+*/
+pub trait MessageCatalog {
+	/// Renders the message of an error kind, without the name of the macro that raised it.
+	fn message(&self, kind: &MacroErrorKind) -> String;
+
+	/// Renders an error, including the name of the macro that raised it.
+	///
+	/// By default, this has the same format as the [`std::fmt::Display`] implementation of [`MacroError`].
+	fn error(&self, error: &MacroError) -> String {
+		format!("error in macro {}: {}", error.name, self.message(&error.error_type))
+	}
+}
+
+/// The default message catalog, which renders errors in English.
+///
+/// This uses the [`std::fmt::Display`] implementation of [`MacroErrorKind`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Hash)]
+pub struct English;
+
+impl MessageCatalog for English {
+	fn message(&self, kind: &MacroErrorKind) -> String {
+		kind.to_string()
+	}
+}
@@ -0,0 +1,262 @@
+/*!
+Contains items for choosing how the standard library computes with numbers.
+
+If you want exact integer arithmetic, see the documentation of [`NumberMode`].
+*/
+
+/**
+How the numeric macros of the standard library compute, set with [`crate::Engine::with_number_mode`].
+
+By default, every number is parsed as an `f64`, so large integers silently lose precision.
+With the `bigint` feature, [`NumberMode::Exact`] keeps integers exact instead.
+
+In exact mode, `add`, `subtract`, `multiply`, `divide`, `pow`, `mod` and `abs` work on integers of any size
+as long as all of their arguments are integers, and only use floats when an argument is one,
+when a division doesn't come out even, when a power has a negative exponent,
+or when a product or power would have more than 65536 bits.
+Comparisons between integers and floats are exact, and `int`, `hex`, `bin` and `oct` convert integers of any size,
+writing negative integers with a minus sign.
+
+Macros called directly through [`crate::Macro::apply`], outside of an engine, always use floats.
+
+## Example
+```
+#    use macroscript::{Engine, add_stdlib};
+#    use std::collections::HashMap;
+#
+# #[cfg(feature = "bigint")]
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+use macroscript::number::NumberMode;
+
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros);
+assert_eq!("9007199254740992", engine.apply("[add/9007199254740993/0]".into())?);
+let engine = engine.with_number_mode(NumberMode::Exact);
+assert_eq!("9007199254740993", engine.apply("[add/9007199254740993/0]".into())?);
+assert_eq!("1267650600228229401496703205376", engine.apply("[pow/2/100]".into())?);
+assert_eq!("9007199254740993", engine.apply("[abs/-9007199254740993]".into())?);
+assert_eq!("2.5", engine.apply("[divide/5/2]".into())?);
+assert_eq!("4", engine.apply("[divide/8/2]".into())?);
+assert_eq!("3.5", engine.apply("[add/1/2.5]".into())?);
+assert_eq!("true", engine.apply("[greater/9007199254740993/9007199254740992.0]".into())?);
+assert_eq!("12345678901234567890", engine.apply("[int/12345678901234567890]".into())?);
+assert_eq!("-54", engine.apply("[int/-54.7]".into())?);
+assert_eq!("FFFFFFFFFFFFFFFFFFFF", engine.apply("[hex/1208925819614629174706175]".into())?);
+#        Ok(()) }
+# #[cfg(not(feature = "bigint"))]
+# fn main() {}
+```
+*/
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum NumberMode {
+	/// Every number is an `f64`.
+	#[default]
+	Float,
+	/// Integers are exact and unbounded, and floats are only used when needed.
+	#[cfg(feature = "bigint")]
+	Exact
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) use exact::Number;
+
+#[cfg(feature = "bigint")]
+mod exact {
+	use std::{cmp::Ordering, str::FromStr};
+	use num_bigint::BigInt;
+	use num_traits::{Euclid, FromPrimitive, Num, Signed, ToPrimitive, Zero};
+
+	/// Products and powers whose result would have more bits than this are computed as floats instead.
+	const MAX_BITS: u64 = 1 << 16;
+
+	/// A number in [`super::NumberMode::Exact`], which is an integer whenever it can be.
+	#[derive(Debug, Clone, PartialEq)]
+	pub(crate) enum Number {
+		Int(BigInt),
+		Float(f64)
+	}
+
+	impl FromStr for Number {
+		type Err = ();
+
+		fn from_str(string: &str) -> Result<Self, ()> {
+			// Digit separators are accepted by `BigInt`, but not by `f64`
+			if string.contains('_') {
+				return Err(());
+			}
+			match BigInt::from_str(string) {
+				Ok(int) => Ok(Number::Int(int)),
+				Err(_) => f64::from_str(string).map(Number::Float).map_err(|_| ())
+			}
+		}
+	}
+
+	impl std::fmt::Display for Number {
+	    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+			match self {
+				Number::Int(int) => write!(f, "{int}"),
+				Number::Float(float) => write!(f, "{float}")
+			}
+		}
+	}
+
+	impl From<u32> for Number {
+		fn from(value: u32) -> Self {
+			Number::Int(value.into())
+		}
+	}
+
+	impl Number {
+		/// Parses an integer in the given base, which must be between 2 and 36.
+		pub(crate) fn from_str_radix(string: &str, base: u32) -> Option<Self> {
+			if string.contains('_') {
+				return None;
+			}
+			BigInt::from_str_radix(string, base).ok().map(Number::Int)
+		}
+
+		/// Renders this number in the given base, if it's an integer.
+		pub(crate) fn to_str_radix(&self, base: u32) -> Option<String> {
+			match self {
+				Number::Int(int) => Some(int.to_str_radix(base)),
+				Number::Float(_) => None
+			}
+		}
+
+		/// Converts this number to a float, possibly losing precision.
+		pub(crate) fn to_f64(&self) -> f64 {
+			match self {
+				Number::Int(int) => int.to_f64().unwrap_or(f64::NAN),
+				Number::Float(float) => *float
+			}
+		}
+
+		/// Truncates this number to an integer, if it's finite.
+		pub(crate) fn trunc(self) -> Option<Self> {
+			match self {
+				Number::Int(int) => Some(Number::Int(int)),
+				Number::Float(float) => BigInt::from_f64(float.trunc()).map(Number::Int)
+			}
+		}
+
+		/// Combines two numbers, exactly if both are integers, and as floats otherwise.
+		fn combine(
+			&self, other: &Self, int: impl FnOnce(&BigInt, &BigInt) -> Option<BigInt>, float: impl FnOnce(f64, f64) -> f64
+		) -> Self {
+			if let (Number::Int(a), Number::Int(b)) = (self, other) {
+				if let Some(result) = int(a, b) {
+					return Number::Int(result);
+				}
+			}
+			Number::Float(float(self.to_f64(), other.to_f64()))
+		}
+
+		pub(crate) fn add(&self, other: &Self) -> Self {
+			self.combine(other, |a, b| Some(a + b), |a, b| a + b)
+		}
+
+		pub(crate) fn sub(&self, other: &Self) -> Self {
+			self.combine(other, |a, b| Some(a - b), |a, b| a - b)
+		}
+
+		/// Multiplies two numbers, staying an integer if the product is small enough.
+		pub(crate) fn mul(&self, other: &Self) -> Self {
+			self.combine(other, |a, b| (a.bits().saturating_add(b.bits()) <= MAX_BITS).then(|| a * b), |a, b| a * b)
+		}
+
+		/// Divides two numbers, staying an integer if the division comes out even.
+		pub(crate) fn div(&self, other: &Self) -> Self {
+			self.combine(
+				other,
+				|a, b| (!b.is_zero() && (a % b).is_zero()).then(|| a / b),
+				|a, b| a / b
+			)
+		}
+
+		/// Takes the euclidean remainder of two numbers, which is never negative.
+		pub(crate) fn rem_euclid(&self, other: &Self) -> Self {
+			self.combine(other, |a, b| (!b.is_zero()).then(|| a.rem_euclid(b)), f64::rem_euclid)
+		}
+
+		/// Raises a number to the power of another, staying an integer if the exponent is a small enough natural number.
+		pub(crate) fn pow(&self, other: &Self) -> Self {
+			self.combine(
+				other,
+				|a, b| {
+					let exp = b.to_u32()?;
+					let bits = a.bits().max(1).saturating_mul(u64::from(exp));
+					(bits <= MAX_BITS).then(|| a.pow(exp))
+				},
+				f64::powf
+			)
+		}
+
+		pub(crate) fn abs(&self) -> Self {
+			match self {
+				Number::Int(int) => Number::Int(int.abs()),
+				Number::Float(float) => Number::Float(float.abs())
+			}
+		}
+
+		/// Compares two numbers exactly, even if only one of them is an integer.
+		pub(crate) fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+			match (self, other) {
+				(Number::Int(a), Number::Int(b)) => Some(a.cmp(b)),
+				(Number::Int(int), Number::Float(float)) => cmp_int_float(int, *float),
+				(Number::Float(float), Number::Int(int)) => cmp_int_float(int, *float).map(Ordering::reverse),
+				(Number::Float(a), Number::Float(b)) => a.partial_cmp(b)
+			}
+		}
+	}
+
+	/// Compares an integer with a float without rounding the integer.
+	fn cmp_int_float(int: &BigInt, float: f64) -> Option<Ordering> {
+		if float.is_nan() {
+			return None;
+		}
+		if float.is_infinite() {
+			return Some(if float.is_sign_positive() { Ordering::Less } else { Ordering::Greater });
+		}
+		let floor = BigInt::from_f64(float.floor())?;
+		match int.cmp(&floor) {
+			// The float is only equal if it has no fractional part
+			Ordering::Equal if float.fract() != 0. => Some(Ordering::Less),
+			ordering => Some(ordering)
+		}
+	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+
+		fn number(string: &str) -> Number {
+			string.parse().unwrap()
+		}
+
+		#[test]
+		fn exact_test() {
+			assert_eq!("9007199254740994", number("9007199254740993").add(&number("1")).to_string());
+			assert_eq!("-3", number("-3").sub(&number("0")).to_string());
+			assert_eq!("0.5", number("1").div(&number("2")).to_string());
+			assert_eq!("inf", number("1").div(&number("0")).to_string());
+			assert_eq!("2", number("-3").rem_euclid(&number("5")).to_string());
+			assert_eq!("NaN", number("3").rem_euclid(&number("0")).to_string());
+			assert_eq!("0.25", number("2").pow(&number("-2")).to_string());
+			assert_eq!("inf", number("10").pow(&number("100000")).to_string());
+			let big = number("2").pow(&number("30000"));
+			let square = big.mul(&big);
+			assert!(matches!(square, Number::Int(_)));
+			assert_eq!("inf", square.mul(&big).to_string());
+			assert_eq!("9007199254740993", number("-9007199254740993").abs().to_string());
+			assert_eq!("2.5", number("-2.5").abs().to_string());
+			assert_eq!(Some(Ordering::Greater), number("3").partial_cmp(&number("2.5")));
+			assert_eq!(Some(Ordering::Less), number("2").partial_cmp(&number("2.5")));
+			assert_eq!(Some(Ordering::Equal), number("2").partial_cmp(&number("2.0")));
+			assert_eq!(Some(Ordering::Less), number("-3").partial_cmp(&number("-2.5")));
+			assert_eq!(None, number("2").partial_cmp(&number("nan")));
+			assert_eq!(Some(Ordering::Less), number("2").partial_cmp(&number("inf")));
+		}
+	}
+}
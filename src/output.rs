@@ -0,0 +1,117 @@
+/*!
+Contains items for output that doesn't affect the result of a script.
+
+Scripts can print and log with the `print` and `log_at` core macros, and the engine emits warnings.
+If you want to receive these as they happen, see the documentation of [`OutputSink`].
+*/
+
+use std::{cell::RefCell, str::FromStr};
+
+/// A piece of side-channel output from an evaluation.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[allow(missing_docs)]
+pub enum Output {
+	/// Text printed by `print`.
+	Print(String),
+	/// A message logged by `log_at`.
+	Log { level: LogLevel, message: String },
+	/// A warning emitted by the engine.
+	Warning(Warning)
+}
+
+/// The severity of a message logged by `log_at`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+	/// `debug`
+	Debug,
+	/// `info`
+	Info,
+	/// `warn`
+	Warn,
+	/// `error`
+	Error
+}
+
+impl FromStr for LogLevel {
+	type Err = ();
+
+	fn from_str(level: &str) -> Result<Self, Self::Err> {
+		match level {
+			"debug" => Ok(LogLevel::Debug),
+			"info" => Ok(LogLevel::Info),
+			"warn" => Ok(LogLevel::Warn),
+			"error" => Ok(LogLevel::Error),
+			_ => Err(())
+		}
+	}
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			LogLevel::Debug => "debug",
+			LogLevel::Info => "info",
+			LogLevel::Warn => "warn",
+			LogLevel::Error => "error"
+		})
+	}
+}
+
+/// A possible mistake in a script, noticed by the engine.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[allow(missing_docs)]
+pub enum Warning {
+	/// The output has an unescaped bracket without a partner, at the given byte position.
+	UnmatchedBracket { position: usize }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Warning::UnmatchedBracket { position } =>
+				write!(f, "unmatched bracket at byte {position} of the output")
+		}
+	}
+}
+
+/**
+Receives side-channel output from evaluations as it happens.
+
+If no sink is set on the [`crate::Engine`], output is collected by each [`crate::Evaluation`] instead,
+and can be read with [`crate::Evaluation::outputs`].
+
+Sinks are shared by every evaluation of an engine, so they take `&self`.
+A sink is implemented for `RefCell<Vec<Output>>`, which collects everything into a list.
+
+## Example
+```
+#    use macroscript::{Engine, add_stdlib, output::{Output, LogLevel, Warning}};
+#    use std::{collections::HashMap, cell::RefCell};
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let sink = RefCell::new(Vec::new());
+let engine = Engine::new(&macros).with_sink(&sink);
+assert_eq!("5]", engine.apply("[print/hello]5[log_at/warn/careful]]".into())?);
+assert_eq!(vec![
+    Output::Print("hello".into()),
+    Output::Log { level: LogLevel::Warn, message: "careful".into() },
+    Output::Warning(Warning::UnmatchedBracket { position: 1 })
+], sink.into_inner());
+#        Ok(()) }
+```
+*/
+pub trait OutputSink {
+	/// Receives a piece of output.
+	fn emit(&self, output: Output);
+}
+
+impl OutputSink for RefCell<Vec<Output>> {
+	fn emit(&self, output: Output) {
+		self.borrow_mut().push(output);
+	}
+}
@@ -1,7 +1,6 @@
 //! Handles parsing of a macro step.
 
 use std::{
-	collections::VecDeque,
 	ops::Range,
 	borrow::Cow
 };
@@ -55,30 +54,55 @@ fn find_innermost_brackets(string: &str) -> Option<Range<usize>> {
 	None
 }
 
+/// Finds the positions of all unescaped square brackets.
+///
+/// In a string without any macro pairs left, these are all brackets without a partner.
+pub(crate) fn find_brackets(string: &str) -> Vec<usize> {
+	let mut last_escaped = false;
+	let mut positions = Vec::new();
+	for (idx, chr) in string.char_indices() {
+		if last_escaped {
+			last_escaped = false;
+			continue;
+		}
+		last_escaped = chr == '\\';
+		if chr == '[' || chr == ']' {
+			positions.push(idx);
+		}
+	}
+	positions
+}
+
 /// Splits the inside of macro brackets into its name and arguments.
 fn split_arguments(inside: &str) -> (&str, Vec<&str>) {
-	let mut argument_spans = VecDeque::new();
+	let mut arguments = split_list(inside).into_iter();
+	// This should be fine
+	let name = arguments.next().expect("splitting always gives at least one item");
+	(name, arguments.collect())
+}
+
+/// Splits a string at every unescaped slash, keeping escapes in the items.
+pub(crate) fn split_list(list: &str) -> Vec<&str> {
+	let mut argument_spans = Vec::new();
 	let mut last_escaped = false;
 	let mut old_start = 0usize;
-	for (idx, char) in inside.char_indices() {
+	for (idx, char) in list.char_indices() {
 		if last_escaped {
 			last_escaped = false;
 			continue;
 		}
 		last_escaped = char == '\\';
 		if char == '/' {
-			argument_spans.push_back(old_start .. idx);
+			argument_spans.push(old_start .. idx);
 			old_start = idx + 1;
 		}
 	}
-	argument_spans.push_back(old_start .. inside.len());
-	// This should be fine
-	let name = argument_spans.pop_front().expect("we just pushed something");
-	(&inside[name], argument_spans.into_iter().map(|range| &inside[range]).collect())
+	argument_spans.push(old_start .. list.len());
+	argument_spans.into_iter().map(|range| &list[range]).collect()
 }
 
 /// Unescapes a borrowed string, returning the borrow if they're the same.
-pub(crate) fn unescape(original: &str) -> Cow<str> {
+pub(crate) fn unescape(original: &str) -> Cow<'_, str> {
 	let mut found_escape = false;
 	let mut last_escape = false;
 	let mut string = String::new();
@@ -114,4 +138,11 @@ mod test {
 		assert_eq!(find_innermost_brackets(r"only open [[[ \]"), None);
 		assert_eq!(find_innermost_brackets(r"[ no close \]\]"), None);
 	}
+
+	#[test]
+	fn stray_bracket_test() {
+		assert_eq!(find_brackets(r"only open [[[ \]"), vec![10, 11, 12]);
+		assert_eq!(find_brackets(r"\[\\]"), vec![4]);
+		assert_eq!(find_brackets(r"none \[ \]"), Vec::<usize>::new());
+	}
 }
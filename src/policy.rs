@@ -0,0 +1,117 @@
+/*!
+Contains items for restricting what a script is allowed to do.
+
+If you want to sandbox untrusted scripts, see the documentation of [`Policy`].
+*/
+
+use std::collections::{HashMap, HashSet};
+
+/// A capability a macro needs in order to run, used to allow or deny groups of macros at once.
+///
+/// Macros declare their capabilities through [`crate::Macro::capabilities`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Capability {
+	/// Reading variables, e.g. `load`.
+	VariableRead,
+	/// Creating, changing or deleting variables, e.g. `store`.
+	VariableWrite,
+	/// Using randomness, e.g. `rand`.
+	Random,
+	/// Compiling user-supplied regular expressions, e.g. `replace`.
+	Regex,
+	/// Suspending the evaluation to ask the host for input, e.g. `input`.
+	Input,
+	/// Emitting side-channel output to the host, e.g. `print`.
+	Output
+}
+
+/**
+Restricts which macros a script may call, and how often.
+
+A policy is checked before every macro call, including core macros and calls inside `try`.
+Calls that aren't allowed fail with [`crate::MacroErrorKind::NotPermitted`],
+and calls over a macro's quota fail with [`crate::MacroErrorKind::QuotaExceeded`].
+Quotas are counted per evaluation.
+
+Macros a policy forbids are never suggested as close names for macros that don't exist.
+
+The default policy allows everything.
+
+## Example
+```
+#    use macroscript::{Engine, MacroErrorKind, add_stdlib, policy::{Policy, Capability}};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let policy = Policy::default()
+    .deny("rand")
+    .deny_capability(Capability::VariableWrite)
+    .quota("add", 2);
+let engine = Engine::new(&macros).with_policy(policy);
+assert_eq!("3", engine.apply("[add/1/[add/1/1]]".into())?);
+let err = engine.apply("[add/1/[add/1/[add/1/1]]]".into()).unwrap_err();
+assert_eq!(MacroErrorKind::QuotaExceeded { limit: 2 }, err.error_type);
+let err = engine.apply("[store/x/5]".into()).unwrap_err();
+assert_eq!(MacroErrorKind::NotPermitted, err.error_type);
+assert_eq!("error in macro rand: not permitted", engine.apply("[rand]".into()).unwrap_err().to_string());
+let engine = Engine::new(&macros).with_policy(Policy::default().deny_capability(Capability::Random));
+let err = engine.apply("[rnd]".into()).unwrap_err();
+assert_eq!(MacroErrorKind::Nonexistent { suggestions: vec!["and".into()] }, err.error_type);
+#        Ok(()) }
+```
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Policy {
+	allowed: Option<HashSet<String>>,
+	denied: HashSet<String>,
+	denied_capabilities: HashSet<Capability>,
+	quotas: HashMap<String, usize>
+}
+
+impl Policy {
+	/// Only allows macros with the given names to be called.
+	///
+	/// Calling this more than once allows the names from every call.
+	#[must_use]
+	pub fn allow_only<I: IntoIterator<Item = S>, S: Into<String>>(mut self, names: I) -> Self {
+		self.allowed.get_or_insert_with(HashSet::new).extend(names.into_iter().map(Into::into));
+		self
+	}
+
+	/// Denies calling the macro with the given name.
+	#[must_use]
+	pub fn deny(mut self, name: impl Into<String>) -> Self {
+		self.denied.insert(name.into());
+		self
+	}
+
+	/// Denies calling any macro that needs the given capability.
+	#[must_use]
+	pub fn deny_capability(mut self, capability: Capability) -> Self {
+		self.denied_capabilities.insert(capability);
+		self
+	}
+
+	/// Limits how many times the macro with the given name can be called in one evaluation.
+	#[must_use]
+	pub fn quota(mut self, name: impl Into<String>, limit: usize) -> Self {
+		self.quotas.insert(name.into(), limit);
+		self
+	}
+
+	/// Returns whether a macro with the given name and capabilities may be called.
+	#[must_use]
+	pub fn permits(&self, name: &str, capabilities: &[Capability]) -> bool {
+		self.allowed.as_ref().is_none_or(|allowed| allowed.contains(name))
+			&& !self.denied.contains(name)
+			&& !capabilities.iter().any(|cap| self.denied_capabilities.contains(cap))
+	}
+
+	/// Gets the quota of the macro with the given name, if it has one.
+	#[must_use]
+	pub fn quota_of(&self, name: &str) -> Option<usize> {
+		self.quotas.get(name).copied()
+	}
+}
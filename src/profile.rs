@@ -0,0 +1,114 @@
+/*!
+Contains items for finding out where an evaluation spends its time.
+
+If you want to profile a script, see the documentation of [`Profile`].
+*/
+
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+/**
+A report of the time spent in each macro during an [`crate::Evaluation`] with [profiling](crate::Evaluation::profiling) on.
+
+Times are wall-clock times. The total time of `try` and `catch` includes the calls inside them,
+and their self time doesn't. For every other macro, the two are the same.
+
+The [`std::fmt::Display`] implementation renders the report as a table, slowest macros first.
+
+## Example
+```
+#    use macroscript::{Engine, add_stdlib};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros);
+let mut evaluation = engine.evaluate(r"[try/\[add\/1\/\[add\/2\/3\]\]]".into()).profiling(true);
+evaluation.run()?;
+let profile = evaluation.profile().unwrap();
+assert_eq!(2, profile.macros["add"].calls);
+assert_eq!(2, profile.macros["add"].bytes);
+assert_eq!(6, profile.macros["try"].bytes);
+assert!(profile.macros["try"].total >= profile.macros["add"].total);
+assert_eq!(5, profile.rescans);
+println!("{profile}");
+#        Ok(()) }
+```
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Profile {
+	/// The statistics of each macro that was called, by the name it was called with.
+	pub macros: HashMap<String, MacroProfile>,
+	/// The amount of times the string being rewritten was scanned for the next macro call.
+	pub rescans: usize
+}
+
+/// The statistics of a single macro in a [`Profile`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Hash)]
+pub struct MacroProfile {
+	/// The amount of times the macro was called, including failed calls.
+	pub calls: usize,
+	/// The time spent in the macro, including calls inside it.
+	pub total: Duration,
+	/// The time spent in the macro, excluding calls inside it.
+	pub self_time: Duration,
+	/// The amount of bytes the macro's successful calls were replaced with.
+	pub bytes: usize
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let mut rows: Vec<_> = self.macros.iter().collect();
+		rows.sort_by(|(a_name, a), (b_name, b)| b.total.cmp(&a.total).then_with(|| a_name.cmp(b_name)));
+		let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("macro".len());
+		writeln!(f, "{:<width$}  {:>8}  {:>12}  {:>12}  {:>10}", "macro", "calls", "total", "self", "bytes")?;
+		for (name, stats) in rows {
+			writeln!(
+				f, "{name:<width$}  {:>8}  {:>12}  {:>12}  {:>10}",
+				stats.calls, format!("{:?}", stats.total), format!("{:?}", stats.self_time), stats.bytes
+			)?;
+		}
+		write!(f, "rescans: {}", self.rescans)
+	}
+}
+
+/// Builds a [`Profile`] while an evaluation runs.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+	pub(crate) profile: Profile,
+	/// The start time of each `try` or `catch` call that's still running, and the total time of the calls inside it.
+	open: Vec<(Instant, Duration)>
+}
+
+impl Profiler {
+	/// Records a call that finished immediately, and returned a string with the given length if it succeeded.
+	pub(crate) fn record(&mut self, name: &str, time: Duration, bytes: Option<usize>) {
+		let stats = self.profile.macros.entry(name.to_string()).or_default();
+		stats.calls += 1;
+		stats.total += time;
+		stats.self_time += time;
+		stats.bytes += bytes.unwrap_or(0);
+		if let Some((_, children)) = self.open.last_mut() {
+			*children += time;
+		}
+	}
+
+	/// Records a call that pushed a frame, which started at the given time.
+	pub(crate) fn open(&mut self, name: &str, start: Instant) {
+		self.profile.macros.entry(name.to_string()).or_default().calls += 1;
+		self.open.push((start, Duration::ZERO));
+	}
+
+	/// Records the end of the innermost frame, whose result has the given length if it succeeded.
+	pub(crate) fn close(&mut self, name: &str, bytes: Option<usize>) {
+		let Some((start, children)) = self.open.pop() else { return };
+		let total = start.elapsed();
+		let stats = self.profile.macros.entry(name.to_string()).or_default();
+		stats.total += total;
+		stats.self_time += total.saturating_sub(children);
+		stats.bytes += bytes.unwrap_or(0);
+		if let Some((_, children)) = self.open.last_mut() {
+			*children += total;
+		}
+	}
+}
@@ -24,7 +24,15 @@ use rand::{Rng, SeedableRng};
 use seahash::SeaHasher;
 use regex::Regex;
 
-use crate::{execution::{Macro, MacroError, MacroErrorKind}, parsing::unescape, TextMacro};
+use crate::{
+    execution::{Macro, MacroError, MacroErrorKind, Limit, Context, handle_id},
+    parsing::{unescape, split_list},
+    policy::Capability,
+    TextMacro,
+    apply_macros
+};
+#[cfg(feature = "bigint")]
+use crate::number::{Number, NumberMode};
 
 macro_rules! count {
     ($tt: tt $($tts: tt)*) => {
@@ -34,13 +42,13 @@ macro_rules! count {
 }
 
 macro_rules! builtin_macros {
-    ($($(#[$attr: meta])* macro $id: ident as $name: literal {$inner: item})*) => {$(
+    ($($(#[$attr: meta])* macro $id: ident as $name: literal {$($inner: item)*})*) => {$(
         #[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Hash)]
         #[doc = concat!("See the documentation on [`DocumentationHelper`] for documentation on this struct.")]
         pub struct $id;
         
         impl Macro for $id {
-            $inner
+            $($inner)*
         }
     )*
 
@@ -48,7 +56,8 @@ macro_rules! builtin_macros {
         /// Dynamically made for easier browsing.
         /**
 # Core macros
-Even without the standard library, there are a few core macros that are always included. They are as follows:
+Even without the standard library, there are a few core macros that are included by default.
+They can be disabled, renamed, or shadowed by the registry using [`crate::CoreMacros`]. They are as follows:
 
 ## `try`
 Executes some escaped macroscript, and returns a boolean value and output.
@@ -56,6 +65,9 @@ Executes some escaped macroscript, and returns a boolean value and output.
 - If the inner script errors, then the boolean is `false` and the output is the error message.
 - If the inner script succeeds, then the boolean is `true` and the output is the result of the inner script.
 
+Any further arguments are the [kinds](crate::MacroErrorKind::identifier) of errors to catch.
+If any are given, other errors aren't caught.
+
 This is reminiscent of Lua's `pcall` function.
 
 ### Examples
@@ -63,6 +75,43 @@ This is reminiscent of Lua's `pcall` function.
 # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
 [try/\[add\/5\/5\]] -> true/10
 [try/\[shl\/5\/100\]] -> false/shift amount of 100 is too large
+[try/\[shl\/5\/100\]/limit_exceeded] -> false/shift amount of 100 is too large
+[try/\[shl\/5\/100\]/conversion] -> error: shift amount of 100 is too large
+<[try/\[try\/\\\[add\\\/1\\\/1\\\]\]]> -> <true/true/2>
+# "#)}
+```
+
+## `catch`
+Executes some escaped macroscript, and if it errors, replaces the call with the result of an escaped handler.
+
+The handler is a [text macro](crate::TextMacro), and receives the following arguments, escaped:
+1. The name of the macro that failed.
+2. The kind of error that occurred, as given by [`crate::MacroErrorKind::identifier`].
+3. The error message.
+
+The handler is only replaced once, so strings like `$1` in the error message are kept as they are.
+If the inner script succeeds, the call is replaced with its result.
+Like `try`, any further arguments are the kinds of errors to catch.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[catch/\[add\/5\/5\]/$3] -> 10
+[catch/\[load\/x\]/$1 failed with $2] -> load failed with undefined_variable
+[catch/\[select\/5\]/$2] -> out_of_bounds
+[catch/\[error\/costs $1\]/msg=$3] -> msg=costs $1
+[catch/\[throw\/$2\/oops $3\]/$2 = $3] -> $2 = $2: oops $3
+# "#)}
+```
+
+## `rethrow`
+Raises the last error caught by `try` or `catch` again, with its original macro name and kind.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[catch/\[error\/oops\]/\[rethrow\]] -> error: oops
+[rethrow] -> error: no error has been caught
 # "#)}
 ```
 
@@ -74,6 +123,7 @@ Loads a variable's value and returns it. Errors if the variable doesn't exist.
 # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
 [load/x] -> error: variable "x" does not currently exist
 [store/x/5][load/x] -> 5
+[store/xy/5][load/x] -> error: variable "x" does not currently exist, did you mean "xy"?
 # "#)}
 ```
 
@@ -118,6 +168,146 @@ Returns whether a variable currently exists.
 [is_stored/x] -> false
 [store/x/5][is_stored/x] -> true
 # "#)}
+```
+
+## `input`
+Suspends the evaluation to ask the host for a value, which replaces the call.
+The arguments, such as a prompt, are passed to the host.
+
+This only works in a [suspendable evaluation](crate::Evaluation#suspending), and errors otherwise.
+
+### Example
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[input/what's your name?] -> error: cannot ask for input here
+# "#)}
+```
+
+## `print`
+Emits its arguments, joined by slashes, to the host as [side-channel output](crate::output), and returns nothing.
+
+### Example
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+a[print/hello/world]b -> ab
+# "#)}
+```
+
+## `log_at`
+Emits a message to the host as [side-channel output](crate::output) with a log level,
+which is one of `debug`, `info`, `warn` or `error`, and returns nothing.
+
+This isn't named `log`, since that name is taken by the logarithm macro below.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[log_at/info/starting]ok -> ok
+[log_at/loud/hello] -> error: invalid log level "loud"
+# "#)}
+```
+
+## `lambda`
+Creates an anonymous macro, and returns a handle to it, like `λ0`.
+The last argument is the macro's body, an escaped [text macro](crate::TextMacro),
+and the ones before it are the names of its parameters, which it must be called with exactly.
+
+Calling the handle like any other macro calls the lambda, so handles can be stored in variables and passed around.
+Macros in the standard library that take an escaped text macro, like `map` and `fold`, also take a handle.
+
+A parameter name starting with `&` instead captures the current value of the variable with that name,
+which the body can use by name like a parameter. Captured values come after the arguments, so `$0` and `$#` include them.
+Capturing needs [`crate::policy::Capability::VariableRead`].
+
+Handles only last until the end of the evaluation that made them.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[lambda/x/\[multiply\/$x\/2\]] -> λ0
+[[lambda/x/\[multiply\/$x\/2\]]/21] -> 42
+[store/double/[lambda/x/$x$x]][[load/double]/ab] -> abab
+[store/n/3][fold/[lambda/a/b/&n/\[add\/$a\/\[multiply\/$b\/$n\]\]]/0/1/2] -> 9
+[store/n/1][store/f/[lambda/x/&n/\[add\/$x\/$n\]]][store/n/100][[load/f]/1] -> 2
+[[lambda/x/y/$x]/1] -> error: expected 2 arguments, found 1, missing "y"
+[[lambda/x/$x]/1/2] -> error: expected at most 1 arguments, found 2
+[λ0/1] -> error: not found
+# "#)}
+```
+
+## `call`
+Calls the macro named by the first argument with the rest of the arguments, and returns its result.
+The name can be anything a call could use, including core macros and lambda handles.
+The arguments are passed on exactly as they are, so nothing needs to be escaped.
+Like evaluations inside of other macros, calls through `call` and `apply` can only be nested 64 deep.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[call/add/1/2] -> 3
+[call/call/apply/add/1\/2] -> 3
+[store/op/multiply][call/[load/op]/3/4] -> 12
+[call/[lambda/x/y/$y$x]/a/b] -> ba
+[call/try/\[error\/oops\]] -> false/oops
+[call/nope] -> error: not found
+# "#)}
+```
+
+```
+#    use macroscript::{apply_macros, add_stdlib, Limit, MacroErrorKind};
+#    use std::collections::HashMap;
+#
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let chain = format!("[{}add/1]", "call/".repeat(10_000));
+let err = apply_macros(chain, &macros).unwrap_err();
+assert_eq!(MacroErrorKind::LimitExceeded { limit: Limit::Nesting, value: 64 }, err.error_type);
+```
+
+## `apply`
+Calls the macro named by the first argument with the items of the escaped list in the second argument as its arguments,
+and returns its result. An empty list passes no arguments.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[apply/add/1\/2] -> 3
+[apply/join/-\/a\/b\/c] -> a-b-c
+[apply/select/2\/a\/b\\\/c] -> b\/c
+[apply/join/] -> error: expected 1 arguments, found 0
+# "#)}
+```
+
+## `partial`
+Returns a handle to a macro with its first arguments fixed, like the handles made by `lambda`.
+Calling the handle calls the macro named by the first argument with the rest of the arguments,
+followed by the ones the handle was called with.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[call/[partial/multiply/2]/21] -> 42
+[map/[partial/multiply/2]/1/2/3] -> 2/4/6
+[store/greet/[partial/join/ /Hello,]][[load/greet]/world] -> Hello, world
+[[partial/add]/1/2] -> 3
+[partial] -> error: expected 1 arguments, found 0
+# "#)}
+```
+
+## `compose`
+Returns a handle that chains the macros named by the arguments, like the handles made by `lambda`.
+Calling the handle calls the last macro with the arguments it was called with,
+then each macro before it with the result of the one after it.
+
+### Examples
+```
+# use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+[[compose/abs/subtract]/1/5] -> 4
+[[compose/upper/reverse]/a/b] -> B/A
+[map/[compose/abs/[partial/subtract/1]]/0/3] -> 1/2
+[[compose/len/[partial/join/-]/reverse]/ab/c] -> 4
+[compose/abs] -> error: expected 2 arguments, found 1
+# "#)}
 ```
          */
         /// ---
@@ -172,9 +362,7 @@ macro_rules! convert_to_number {
         <$ty>::from_str(arg).map_err(|_| {
             MacroError::new(
                 $name.into(),
-                MacroErrorKind::user(
-                    format!("could not convert argument {} \"{arg}\" to {}", $idx, stringify!($ty))
-                   )
+                MacroErrorKind::conversion($idx, arg.to_string(), stringify!($ty))
             )
         })?
     }}
@@ -188,6 +376,54 @@ fn truthy(string: impl AsRef<str>) -> bool {
     }
 }
 
+/// Escapes backslashes, slashes and brackets, so a string can be passed as a single argument.
+fn escape(string: &str) -> String {
+    string.replace('\\', r"\\").replace('/', r"\/").replace('[', r"\[").replace(']', r"\]")
+}
+
+/// Converts an argument to a number in [`NumberMode::Exact`]. The index is one-based.
+#[cfg(feature = "bigint")]
+fn exact_number(name: &str, index: usize, argument: &str) -> Result<Number, MacroError> {
+    argument.parse().map_err(|()| MacroError::new(
+        name.into(), MacroErrorKind::conversion(index, argument, "number")
+    ))
+}
+
+/// Converts an integer argument to a string in another base in [`NumberMode::Exact`]. The index is one-based.
+#[cfg(feature = "bigint")]
+fn exact_radix(name: &str, index: usize, argument: &str, base: u32) -> Result<String, MacroError> {
+    argument.parse::<Number>().ok()
+        .and_then(|number| number.to_str_radix(base))
+        .map(|string| string.to_uppercase())
+        .ok_or_else(|| MacroError::new(name.into(), MacroErrorKind::conversion(index, argument, "integer")))
+}
+
+/// Reads the macro given to a higher-order macro, which is either an escaped text macro or a handle.
+fn text_macro(argument: &str) -> TextMacro {
+    if handle_id(argument).is_some() {
+        TextMacro::new(format!("[{argument}/$0]"))
+    } else {
+        TextMacro::new(unescape(argument))
+    }
+}
+
+/// Substitutes arguments into a text macro, then evaluates the result.
+fn evaluate_with(context: &mut dyn Context, mac: &TextMacro, arguments: Vec<&str>) -> Result<String, MacroError> {
+    context.evaluate(&mac.apply(arguments)?)
+}
+
+/// The context used when a macro that evaluates text macros is applied outside of an engine.
+/// Strings are evaluated with only the standard library, and without any variables.
+struct Standalone;
+
+impl Context for Standalone {
+    fn evaluate(&mut self, input: &str) -> Result<String, MacroError> {
+        let mut macros = HashMap::new();
+        add(&mut macros);
+        apply_macros(input.to_string(), &macros)
+    }
+}
+
 builtin_macros! {
     /// Comment. Returns nothing.
     /// ### Example
@@ -238,9 +474,20 @@ builtin_macros! {
                 .map(|(idx, arg)| {
                     Ok(convert_to_number!("add"; at idx+1 => arg))
                 })
-                .process_results(|iter| iter.sum())
+                .process_results(|iter| iter.fold(0., |a, b| a + b))
                 .map(|sum: f64| sum.to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            arguments
+                .iter()
+                .enumerate()
+                .map(|(idx, arg)| exact_number("add", idx + 1, arg))
+                .process_results(|iter| iter.fold(Number::from(0), |a, b| a.add(&b)))
+                .map(|sum| sum.to_string())
+        }
     }
 
     /// Multiplicaton. Takes 0 or more numeric arguments and returns their product.
@@ -263,6 +510,17 @@ builtin_macros! {
                 .process_results(|iter| iter.product())
                 .map(|product: f64| product.to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            arguments
+                .iter()
+                .enumerate()
+                .map(|(idx, arg)| exact_number("multiply", idx + 1, arg))
+                .process_results(|iter| iter.fold(Number::from(1), |a, b| a.mul(&b)))
+                .map(|product| product.to_string())
+        }
     }
 
     /// Unescapes its input.
@@ -304,12 +562,7 @@ builtin_macros! {
             if let [end] = chunks.remainder() {
                 Ok((*end).to_string())
             } else {
-                Err(MacroError {
-                    name: "if".into(),
-                    error_type: MacroErrorKind::User {
-                        message: "all conditions exhausted".into()
-                    }
-                })
+                Err(MacroError::new("if".into(), MacroErrorKind::ConditionsExhausted))
             }
         }
     }
@@ -360,6 +613,15 @@ builtin_macros! {
                let exp = convert_to_number!("pow"; at 2 => exp);
             Ok(base.powf(exp).to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (lhs, rhs) = get_args!("pow", arguments; a, b);
+            let lhs = exact_number("pow", 1, lhs)?;
+            let rhs = exact_number("pow", 2, rhs)?;
+            Ok(lhs.pow(&rhs).to_string())
+        }
     }
 
     /// Subtracts a number from another.
@@ -377,6 +639,15 @@ builtin_macros! {
                let rhs = convert_to_number!("subtract"; at 2 => rhs);
             Ok((lhs - rhs).to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (lhs, rhs) = get_args!("subtract", arguments; a, b);
+            let lhs = exact_number("subtract", 1, lhs)?;
+            let rhs = exact_number("subtract", 2, rhs)?;
+            Ok(lhs.sub(&rhs).to_string())
+        }
     }
     
     /// Divides a number by another.
@@ -397,6 +668,15 @@ builtin_macros! {
                let rhs = convert_to_number!("divide"; at 2 => rhs);
             Ok((lhs / rhs).to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (lhs, rhs) = get_args!("divide", arguments; a, b);
+            let lhs = exact_number("divide", 1, lhs)?;
+            let rhs = exact_number("divide", 2, rhs)?;
+            Ok(lhs.div(&rhs).to_string())
+        }
     }
     
     /// Takes the modulus of one number with respect to another.
@@ -414,6 +694,15 @@ builtin_macros! {
                let rhs = convert_to_number!("mod"; at 2 => rhs);
             Ok(lhs.rem_euclid(rhs).to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (lhs, rhs) = get_args!("mod", arguments; a, b);
+            let lhs = exact_number("mod", 1, lhs)?;
+            let rhs = exact_number("mod", 2, rhs)?;
+            Ok(lhs.rem_euclid(&rhs).to_string())
+        }
     }
 
     
@@ -461,6 +750,10 @@ builtin_macros! {
                };
                Ok(value.to_string())
         }
+
+        fn capabilities(&self) -> &'static [Capability] {
+            &[Capability::Random]
+        }
     }
 
     /// Hashes many values, returning 64-bit integers.
@@ -497,21 +790,24 @@ builtin_macros! {
             let pattern = unescape(pattern);
             let replacement = unescape(replacement);
                let regex = Regex::new(&pattern).map_err(|err| {
-                let disp = match err {
+                let kind = match err {
                     regex::Error::Syntax(err) => {
-                        let err_string = err.to_string();
-                        let last_line = err_string.lines().last().unwrap();
-                        last_line[7..].to_string()
+                        let last_line = err.lines().last().unwrap();
+                        MacroErrorKind::InvalidRegex { detail: last_line[7..].to_string() }
                     },
                     regex::Error::CompiledTooBig(limit) =>
-                        format!("compiled regex exceeds size limit of {limit} bytes"),
-                    _ => err.to_string()
+                        MacroErrorKind::LimitExceeded { limit: Limit::RegexSize, value: limit },
+                    _ => MacroErrorKind::InvalidRegex { detail: err.to_string() }
                 };
-                MacroError::new("replace".into(), MacroErrorKind::user(disp))
+                MacroError::new("replace".into(), kind)
             })?;
                let res = regex.replace_all(haystack, replacement);
                Ok(res.into_owned())
         }
+
+        fn capabilities(&self) -> &'static [Capability] {
+            &[Capability::Regex]
+        }
     }
 
     /// Converts the input to an integer, with an optional base to convert from.
@@ -529,20 +825,40 @@ builtin_macros! {
                if let Some(base) = arguments.get(1) {
                 let base = convert_to_number!("int"; <u32> at 2 => base);
                 if !(2 ..= 36).contains(&base) {
-                    return Err(MacroError::new("int".into(), MacroErrorKind::user(
-                        format!("invalid base {base} (must be between 2 and 36, inclusive)")
-                    )));
+                    return Err(MacroError::new("int".into(), MacroErrorKind::InvalidBase { base }));
                 }
                  i64::from_str_radix(value, base)
                      .map(|v| v.to_string())
-                     .map_err(|_| MacroError::new("int".into(), MacroErrorKind::user(
-                         format!("failed to convert {value} to a number with base {base}")
-                     )))
+                     .map_err(|_| MacroError::new("int".into(), MacroErrorKind::RadixConversion {
+                         value: (*value).to_string(), base
+                     }))
              } else {
                      let value = convert_to_number!("int"; at 1 => value) as i64;
                    Ok(value.to_string())
                }
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (value, ) = get_args!("int", arguments; value);
+            if let Some(base) = arguments.get(1) {
+                let base = convert_to_number!("int"; <u32> at 2 => base);
+                if !(2 ..= 36).contains(&base) {
+                    return Err(MacroError::new("int".into(), MacroErrorKind::InvalidBase { base }));
+                }
+                Number::from_str_radix(value, base)
+                    .map(|v| v.to_string())
+                    .ok_or_else(|| MacroError::new("int".into(), MacroErrorKind::RadixConversion {
+                        value: (*value).to_string(), base
+                    }))
+            } else {
+                exact_number("int", 1, value)?
+                    .trunc()
+                    .map(|v| v.to_string())
+                    .ok_or_else(|| MacroError::new("int".into(), MacroErrorKind::conversion(1, *value, "integer")))
+            }
+        }
     }
 
     /// Converts the input to a hexadecimal integer.
@@ -560,6 +876,14 @@ builtin_macros! {
                     Ok(format!("{:X}", convert_to_number!("hex"; <i64> at idx + 1 => value)))
                 ).process_results(|mut iter| iter.join("/"))
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            arguments.iter().enumerate()
+                .map(|(idx, value)| exact_radix("hex", idx + 1, value, 16))
+                .process_results(|mut iter| iter.join("/"))
+        }
     }
 
     
@@ -578,6 +902,14 @@ builtin_macros! {
                     Ok(format!("{:b}", convert_to_number!("bin"; <i64> at idx + 1 => value)))
                 ).process_results(|mut iter| iter.join("/"))
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            arguments.iter().enumerate()
+                .map(|(idx, value)| exact_radix("bin", idx + 1, value, 2))
+                .process_results(|mut iter| iter.join("/"))
+        }
     }
 
     
@@ -596,6 +928,14 @@ builtin_macros! {
                     Ok(format!("{:o}", convert_to_number!("oct"; <i64> at idx + 1 => value)))
                 ).process_results(|mut iter| iter.join("/"))
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            arguments.iter().enumerate()
+                .map(|(idx, value)| exact_radix("oct", idx + 1, value, 8))
+                .process_results(|mut iter| iter.join("/"))
+        }
     }
 
     /// Converts a unicode codepoint to a character.
@@ -614,9 +954,9 @@ builtin_macros! {
                 .iter().enumerate()
                 .map(|(idx, chr)| {
                     let ord = convert_to_number!("chr"; <u32> at idx + 1 => *chr);
-                    char::from_u32(ord).ok_or_else(|| MacroError::new("chr".into(), MacroErrorKind::user(
-                        format!("invalid codepoint at argument {}", idx + 1)
-                    )))
+                    char::from_u32(ord).ok_or_else(|| MacroError::new("chr".into(), MacroErrorKind::InvalidCodepoint {
+                        index: idx + 1
+                    }))
                 }).collect()
         }
     }
@@ -669,9 +1009,7 @@ builtin_macros! {
                haystack.split(&**delimiter).nth(index)
                 .map(ToString::to_string)
                    .ok_or_else(|| MacroError::new(
-                       "split".into(), MacroErrorKind::user(
-                           format!("index {index} is out of bounds")
-                       )
+                       "split".into(), MacroErrorKind::out_of_bounds(index, haystack.split(&**delimiter).count())
                    ))
         }        
     }
@@ -698,9 +1036,7 @@ builtin_macros! {
             arguments.get(index)
                 .map(ToString::to_string)
                 .ok_or_else(|| MacroError::new(
-                       "select".into(), MacroErrorKind::user(
-                           format!("index {index} is out of bounds")
-                       )
+                       "select".into(), MacroErrorKind::out_of_bounds(index, arguments.len())
                    ))
         }        
     }
@@ -737,6 +1073,15 @@ builtin_macros! {
             let rhs = convert_to_number!("#equal"; at 2 => rhs);
             Ok((lhs == rhs).to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (lhs, rhs) = get_args!("#equal", arguments; a, b);
+            let lhs = exact_number("#equal", 1, lhs)?;
+            let rhs = exact_number("#equal", 2, rhs)?;
+            Ok((lhs.partial_cmp(&rhs) == Some(std::cmp::Ordering::Equal)).to_string())
+        }
     }
 
     /// Returns whether a number is greater than another.
@@ -755,6 +1100,15 @@ builtin_macros! {
             let rhs = convert_to_number!("greater"; at 2 => rhs);
             Ok((lhs > rhs).to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (lhs, rhs) = get_args!("greater", arguments; a, b);
+            let lhs = exact_number("greater", 1, lhs)?;
+            let rhs = exact_number("greater", 2, rhs)?;
+            Ok((lhs.partial_cmp(&rhs) == Some(std::cmp::Ordering::Greater)).to_string())
+        }
     }
 
     /// Returns whether a number is less than another.
@@ -773,6 +1127,15 @@ builtin_macros! {
             let rhs = convert_to_number!("less"; at 2 => rhs);
             Ok((lhs < rhs).to_string())
         }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            let (lhs, rhs) = get_args!("less", arguments; a, b);
+            let lhs = exact_number("less", 1, lhs)?;
+            let rhs = exact_number("less", 2, rhs)?;
+            Ok((lhs.partial_cmp(&rhs) == Some(std::cmp::Ordering::Less)).to_string())
+        }
     }
 
     /// Negates many boolean inputs.
@@ -940,7 +1303,7 @@ builtin_macros! {
                 .map(|v| (v as i64).to_string())
                 .ok_or_else(|| MacroError::new(
                     "shl".into(),
-                    MacroErrorKind::user(format!("shift amount of {rhs} is too large"))
+                    MacroErrorKind::LimitExceeded { limit: Limit::ShiftAmount, value: rhs as usize }
                 ))
         }
     }
@@ -963,7 +1326,7 @@ builtin_macros! {
                 .map(|v| (v as i64).to_string())
                 .ok_or_else(|| MacroError::new(
                     "shr".into(),
-                    MacroErrorKind::user(format!("shift amount of {rhs} is too large"))
+                    MacroErrorKind::LimitExceeded { limit: Limit::ShiftAmount, value: rhs as usize }
                 ))
         }
     }
@@ -986,7 +1349,7 @@ builtin_macros! {
                 .map(|v| v.to_string())
                 .ok_or_else(|| MacroError::new(
                     "#shr".into(),
-                    MacroErrorKind::user(format!("shift amount of {rhs} is too large"))
+                    MacroErrorKind::LimitExceeded { limit: Limit::ShiftAmount, value: rhs as usize }
                 ))
         }
     }
@@ -1005,7 +1368,15 @@ builtin_macros! {
                 .map(|(idx, value)|
                     Ok(convert_to_number!("abs"; at idx + 1 => value).abs().to_string())
                 ).process_results(|mut iter| iter.join("/"))
-        }    
+        }
+
+        #[cfg(feature = "bigint")]
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            if context.number_mode() == NumberMode::Float { return self.apply(arguments) }
+            arguments.iter().enumerate()
+                .map(|(idx, value)| Ok(exact_number("abs", idx + 1, value)?.abs().to_string()))
+                .process_results(|mut iter| iter.join("/"))
+        }
     }
     
     /// Gets the sine of many numbers.
@@ -1120,6 +1491,29 @@ builtin_macros! {
         }        
     }
 
+    /// Immediately raises an error with a custom kind, given by the first argument.
+    /// The rest of the arguments are the error's payload.
+    ///
+    /// The kind can be used to only catch certain errors with `try` and `catch`.
+    /// ### Examples
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [throw/not_found/id 42] -> error: not_found: id 42
+    /// [throw/oops] -> error: oops
+    /// [catch/\[throw\/not_found\/id 42\]/missing $3/not_found] -> missing not_found: id 42
+    /// [try/\[throw\/not_found\]/invalid_input] -> error: not_found
+    /// # "#)}
+    /// ```
+    macro Throw as "throw" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (kind, ) = get_args!("throw", arguments; a);
+            Err(MacroError::new("throw".into(), MacroErrorKind::Thrown {
+                kind: (*kind).to_string(),
+                payload: arguments[1..].join("/")
+            }))
+        }
+    }
+
     /// Raises an error if the first argument is not truthy.
     /// ### Examples
     /// ```
@@ -1167,9 +1561,7 @@ builtin_macros! {
                     .transpose()?
                     .unwrap_or(1);
             if step == 0 {
-                return Err(MacroError::new("slice".into(), MacroErrorKind::user(
-                    "cannot have a step length of 0"
-                )))
+                return Err(MacroError::new("slice".into(), MacroErrorKind::ZeroStep))
             }
             let Some(slice) = (match (start, end) {
                 (None, None) => Some(&haystack[..]),
@@ -1179,14 +1571,9 @@ builtin_macros! {
                     .and_then(|(s, _)| Some((s, haystack.char_indices().nth(e)?)))
                     .and_then(|(s, (e, _))| haystack.get(s..e))
             }) else {
-                return Err(MacroError::new("slice".into(), MacroErrorKind::user(
-                    format!(
-                        "part of range \"{}..{}\" is out of bounds for string of length {}",
-                        start.map(|v| v.to_string()).unwrap_or_default(),
-                        end.map(|v| v.to_string()).unwrap_or_default(),
-                        haystack.chars().count()
-                    )
-                )))
+                return Err(MacroError::new("slice".into(), MacroErrorKind::RangeOutOfBounds {
+                    start, end, len: haystack.chars().count()
+                }))
             };
             if step == 1 {
                 // Fast path
@@ -1264,8 +1651,7 @@ builtin_macros! {
     /// ```
     macro Escape as "escape" {
         fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
-              let raw = arguments.join("/");
-              Ok(raw.replace('\\', r"\\").replace('/', r"\/").replace('[', r"\[").replace(']', r"\]"))
+              Ok(escape(&arguments.join("/")))
         }
     }
 
@@ -1281,7 +1667,7 @@ builtin_macros! {
         fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
               let (target, count) = get_args!("repeat", arguments; a, b);
             let count = convert_to_number!("repeat"; <usize> at 2 => count);
-            Ok(std::iter::repeat(target).take(count).join(arguments.get(2).map_or("", |v| &**v)))
+            Ok(std::iter::repeat_n(target, count).join(arguments.get(2).map_or("", |v| &**v)))
         }
     }
 
@@ -1314,17 +1700,20 @@ builtin_macros! {
     }
 
     /// Maps an escaped text macro over all of the inputs, returning the results as outputs.
+    ///
+    /// Like every macro here that takes an escaped text macro, this also takes a handle made by `lambda`, `partial` or `compose`.
     /// # Example
     /// ```
     /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
     /// [map/\[multiply\/$1\/2\]/1/2/3] -> 2/4/6
+    /// [map/[lambda/x/\[multiply\/$x\/2\]]/1/2/3] -> 2/4/6
     /// # "#)}
     /// ```
     macro Map as "map" {
         fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
             if arguments.len() == 1 { return Ok(String::new()) }
             let (mac, ) = get_args!("map", arguments; a);
-            let mac = TextMacro::new(unescape(mac));
+            let mac = text_macro(mac);
             arguments
                 .iter()
                 .skip(1)
@@ -1343,11 +1732,300 @@ builtin_macros! {
     macro Fold as "fold" {
         fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
             let (mac, base) = get_args!("map", arguments; a, b);
-            let mac = TextMacro::new(unescape(mac));
+            let mac = text_macro(mac);
             arguments
                 .iter()
                 .skip(2)
                 .try_fold((*base).to_string(), |a, b| mac.apply(vec![&a, *b]))
         }
     }
+
+    /// Maps an escaped text macro over all of the inputs like `map`, but evaluates each result before moving on to the next input.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [map_eager/\[multiply\/$1\/2\]/1/2/3] -> 2/4/6
+    /// [store/n/0][map_eager/\[store\/n\/\[add\/\[load\/n\]\/$1\]\]\[load\/n\]/1/2/3] -> 1/3/6
+    /// # "#)}
+    /// ```
+    macro MapEager as "map_eager" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("map_eager", arguments; a);
+            let mac = text_macro(mac);
+            arguments
+                .iter()
+                .skip(1)
+                .map(|v| evaluate_with(context, &mac, vec![v]))
+                .process_results(|mut v| v.join("/"))
+        }
+    }
+
+    /// Performs a fold like `fold`, but evaluates the accumulator at each step before moving on to the next input.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [fold_eager/\[add\/$1\/$2\]/0/1/2/3] -> 6
+    /// [fold_eager/\[if\/\[greater\/$1\/$2\]\/$1\/$2\]/0/3/7/2] -> 7
+    /// [fold_eager/\[len\/$1$2\]/a/bc/def] -> 4
+    /// # "#)}
+    /// ```
+    macro FoldEager as "fold_eager" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, base) = get_args!("fold_eager", arguments; a, b);
+            let mac = text_macro(mac);
+            arguments
+                .iter()
+                .skip(2)
+                .try_fold((*base).to_string(), |a, b| evaluate_with(context, &mac, vec![&a, *b]))
+        }
+    }
+
+    /// Evaluates the unescaped first argument N times, where N is the second argument,
+    /// optionally joining the results with the third argument.
+    /// Unlike `repeat`, each repetition is evaluated before the next one starts.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [store/x/0][repeat_eager/\[store\/x\/\[add\/\[load\/x\]\/1\]\]\[load\/x\]/5/,] -> 1,2,3,4,5
+    /// [repeat_eager/a/0] -> <no output>
+    /// [repeat_eager/\[nope\]/99999999999999999] -> error: not found
+    /// # "#)}
+    /// ```
+    macro RepeatEager as "repeat_eager" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (target, count) = get_args!("repeat_eager", arguments; a, b);
+            let count = convert_to_number!("repeat_eager"; <usize> at 2 => count);
+            let target = unescape(target);
+            (0..count)
+                .map(|_| context.evaluate(&target))
+                .process_results(|mut v| v.join(arguments.get(2).map_or("", |v| &**v)))
+        }
+    }
+
+    /// Maps an escaped text macro over all of the inputs like `map`, passing the index of each input as `$2`.
+    /// Indices start at 0.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [map_indexed/$2=$1/a/b/c] -> 0=a/1=b/2=c
+    /// [map_indexed/\[multiply\/$1\/$2\]/5/5/5] -> 0/5/10
+    /// # "#)}
+    /// ```
+    macro MapIndexed as "map_indexed" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("map_indexed", arguments; a);
+            let mac = text_macro(mac);
+            arguments
+                .iter()
+                .skip(1)
+                .enumerate()
+                .map(|(idx, v)| mac.apply(vec![v, &idx.to_string()]))
+                .process_results(|mut v| v.join("/"))
+        }
+    }
+
+    /// Maps an escaped text macro over several escaped lists at once,
+    /// passing the Nth item of each list as `$1`, `$2` and so on.
+    /// Stops at the end of the shortest list.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [zip_map/\[add\/$1\/$2\]/1\/2\/3/10\/20\/30] -> 11/22/33
+    /// [zip_map/$1$2$3/a\/b/1\/2\/3/x\/y] -> a1x/b2y
+    /// # "#)}
+    /// ```
+    macro ZipMap as "zip_map" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, _) = get_args!("zip_map", arguments; a, b);
+            let mac = text_macro(mac);
+            let lists: Vec<_> = arguments.iter().skip(1).map(|list| unescape(list)).collect();
+            let lists: Vec<_> = lists.iter().map(|list| split_list(list)).collect();
+            let len = lists.iter().map(Vec::len).min().unwrap_or(0);
+            (0..len)
+                .map(|idx| mac.apply(lists.iter().map(|list| list[idx]).collect()))
+                .process_results(|mut v| v.join("/"))
+        }
+    }
+
+    /// Keeps the inputs for which an escaped text macro evaluates to something truthy.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [filter/\[greater\/$1\/2\]/1/5/2/3] -> 5/3
+    /// [filter/\[equal\/$1\/x\]/a/b] -> <no output>
+    /// # "#)}
+    /// ```
+    macro Filter as "filter" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("filter", arguments; a);
+            let mac = text_macro(mac);
+            let mut kept = Vec::new();
+            for item in &arguments[1..] {
+                if truthy(evaluate_with(context, &mac, vec![item])?) {
+                    kept.push(*item);
+                }
+            }
+            Ok(kept.join("/"))
+        }
+    }
+
+    /// Returns whether an escaped text macro evaluates to something truthy for any of the inputs.
+    /// Stops at the first one that does.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [any/\[greater\/$1\/2\]/1/5/x] -> true
+    /// [any/\[greater\/$1\/2\]/1/2] -> false
+    /// [any/\[greater\/$1\/2\]] -> false
+    /// # "#)}
+    /// ```
+    macro Any as "any" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("any", arguments; a);
+            let mac = text_macro(mac);
+            for item in &arguments[1..] {
+                if truthy(evaluate_with(context, &mac, vec![item])?) {
+                    return Ok("true".into());
+                }
+            }
+            Ok("false".into())
+        }
+    }
+
+    /// Returns whether an escaped text macro evaluates to something truthy for all of the inputs.
+    /// Stops at the first one that doesn't.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [all/\[greater\/$1\/2\]/3/5/4] -> true
+    /// [all/\[greater\/$1\/2\]/3/1/x] -> false
+    /// [all/\[greater\/$1\/2\]] -> true
+    /// # "#)}
+    /// ```
+    macro All as "all" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("all", arguments; a);
+            let mac = text_macro(mac);
+            for item in &arguments[1..] {
+                if !truthy(evaluate_with(context, &mac, vec![item])?) {
+                    return Ok("false".into());
+                }
+            }
+            Ok("true".into())
+        }
+    }
+
+    /// Returns the index of the first input for which an escaped text macro evaluates to something truthy.
+    /// Indices start at 0. Returns -1 if there isn't one.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [find_index/\[greater\/$1\/2\]/1/5/7] -> 1
+    /// [find_index/\[greater\/$1\/2\]/1/2] -> -1
+    /// # "#)}
+    /// ```
+    macro FindIndex as "find_index" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("find_index", arguments; a);
+            let mac = text_macro(mac);
+            for (idx, item) in arguments[1..].iter().enumerate() {
+                if truthy(evaluate_with(context, &mac, vec![item])?) {
+                    return Ok(idx.to_string());
+                }
+            }
+            Ok("-1".into())
+        }
+    }
+
+    /// Sorts the inputs by the result of evaluating an escaped text macro on each of them.
+    /// If every result is a number, they're compared as numbers, and otherwise as strings.
+    /// Inputs with equal results keep their order.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [sort_by/\[len\/$1\]/ccc/a/bb/d] -> a/d/bb/ccc
+    /// [sort_by/$1/10/9/100] -> 9/10/100
+    /// [sort_by/$1/b/10/a/9] -> 10/9/a/b
+    /// [sort_by/\[subtract\/0\/$1\]/1/3/2] -> 3/2/1
+    /// # "#)}
+    /// ```
+    macro SortBy as "sort_by" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("sort_by", arguments; a);
+            let mac = text_macro(mac);
+            let mut keyed = Vec::new();
+            for item in &arguments[1..] {
+                let key = evaluate_with(context, &mac, vec![item])?;
+                keyed.push((f64::from_str(&key).ok(), key, *item));
+            }
+            let numeric = keyed.iter().all(|(number, _, _)| number.is_some());
+            keyed.sort_by(|(a_number, a, _), (b_number, b, _)| match (a_number, b_number) {
+                (Some(a_number), Some(b_number)) if numeric => a_number.total_cmp(b_number),
+                _ => a.cmp(b)
+            });
+            Ok(keyed.into_iter().map(|(_, _, item)| item).join("/"))
+        }
+    }
+
+    /// Groups the inputs by the result of evaluating an escaped text macro on each of them.
+    /// Each group is returned as an escaped list, starting with the result and followed by its inputs.
+    /// Groups are in the order their first input appears.
+    /// # Example
+    /// ```
+    /// # use macroscript::test::test_output; fn main() -> Result<(), Box<dyn std::error::Error>> { test_output(r#"
+    /// [group_by/\[mod\/$1\/2\]/1/2/3/4/5] -> 1\/1\/3\/5/0\/2\/4
+    /// [group_by/\[len\/$1\]/ab/c/de] -> 2\/ab\/de/1\/c
+    /// # "#)}
+    /// ```
+    macro GroupBy as "group_by" {
+        fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
+            self.apply_in(&mut Standalone, arguments)
+        }
+
+        fn apply_in(&self, context: &mut dyn Context, arguments: Vec<&str>) -> Result<String, MacroError> {
+            let (mac, ) = get_args!("group_by", arguments; a);
+            let mac = text_macro(mac);
+            let mut groups: Vec<(String, Vec<&str>)> = Vec::new();
+            for item in &arguments[1..] {
+                let key = escape(&evaluate_with(context, &mac, vec![item])?);
+                match groups.iter_mut().find(|(other, _)| *other == key) {
+                    Some((_, items)) => items.push(item),
+                    None => groups.push((key, vec![item]))
+                }
+            }
+            Ok(groups.into_iter().map(|(key, items)| escape(&format!("{key}/{}", items.join("/")))).join("/"))
+        }
+    }
 }
@@ -6,10 +6,9 @@ If you want help writing a text macro, see the documentation of [`TextMacro`].
 
 use std::{
 	borrow::Cow,
-	cell::LazyCell,
 	str::FromStr
 };
-use crate::{Macro, MacroError};
+use crate::{Macro, MacroError, MacroErrorKind};
 
 /**
 Simplifies creating macros by allowing you to compose them from other macros.
@@ -19,6 +18,23 @@ The following strings are replaced:
 - `$#` Replaced with the amount of arguments.
 - `$0` Replaced with all arguments separated by `/`.
 - `$<num>` Replaced with the argument at the given index (one-based). The argument is not replaced if it doesn't exist.
+- `$-<num>` Replaced with the argument at the given index, counting from the end, so `$-1` is the last argument.
+  The argument is not replaced if it doesn't exist.
+- `${<start>..<end>}` Replaced with the arguments from the start index up to, but not including, the end index, separated by `/`.
+  The end can be left out to go until the last argument, and either index can be negative to count from the end.
+  Indices past either end are clamped, so this is always replaced, possibly with nothing.
+  Ranges are only recognized inside braces, so `$1...` is still the first argument followed by dots.
+
+Any of the others can be surrounded in braces, like `${1}`, to separate them from the text after them.
+Braces also allow handling missing arguments:
+- `${<arg>:<fallback>}` Replaced with the argument if it exists, and otherwise with the fallback.
+- `${<arg>?<then>}` Replaced with `then` if the argument exists, and otherwise with nothing.
+- `${<arg>?<then>:<else>}` Replaced with `then` if the argument exists, and otherwise with `else`.
+
+An argument exists if its index is in bounds, even if it's empty. A range exists if it has any arguments in it.
+The fallback and branches can contain strings to replace as well, including more braces.
+They end at the first `:` or `}` that isn't inside another pair of braces.
+Braces can be nested up to 32 deep, and any deeper ones are left as they are.
 
 The strings are replaced from back to front, and if another one is constructed while replacing them, it will be replaced as well.
 
@@ -31,7 +47,8 @@ The strings are replaced from back to front, and if another one is constructed w
 let mut macros = HashMap::<String, Box<dyn Macro>>::from([
     ("bad_select".to_string(), TextMacro::boxed("$$1")),
     ("escaped_dollar".to_string(), TextMacro::boxed(r"\$1")),
-	("square".to_string(), TextMacro::boxed("[multiply/$1/$1]"))
+    ("square".to_string(), TextMacro::boxed("[multiply/$1/$1]")),
+    ("echo".to_string(), TextMacro::boxed("$1"))
 ]);
 add_stdlib(&mut macros);
 assert_eq!("$1", apply_macros("[escaped_dollar/2]".into(), &macros)?);
@@ -40,36 +57,137 @@ assert_eq!("$3", apply_macros("[bad_select/3]".into(), &macros)?);
 assert_eq!("0/1/2/3", apply_macros("[bad_select/0/1/2/3]".into(), &macros)?);
 assert_eq!("4", apply_macros("[bad_select/#/β/2/3]".into(), &macros)?);
 assert_eq!("16", apply_macros("[square/4]".into(), &macros)?);
+assert_eq!("$1", apply_macros("[echo/$1]".into(), &macros)?);
+assert!(apply_macros("[echo/$1$1]".into(), &macros)?.len() < 1000);
+#        Ok(()) }
+```
+
+### Variadic and optional arguments
+```
+#    use macroscript::{Macro, apply_macros, TextMacro, add_stdlib};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::<String, Box<dyn Macro>>::from([
+    ("last".to_string(), TextMacro::boxed("$-1")),
+    ("rest".to_string(), TextMacro::boxed("${2..}")),
+    ("middle".to_string(), TextMacro::boxed("${2..-1}")),
+    ("loading".to_string(), TextMacro::boxed("Loading $1...")),
+    ("greet".to_string(), TextMacro::boxed("Hello, ${1:world}${2?, from $2}!")),
+    ("plural".to_string(), TextMacro::boxed("${1}${2?s:}"))
+]);
+add_stdlib(&mut macros);
+assert_eq!("c", apply_macros("[last/a/b/c]".into(), &macros)?);
+assert_eq!("b/c", apply_macros("[rest/a/b/c]".into(), &macros)?);
+assert_eq!("", apply_macros("[rest/a]".into(), &macros)?);
+assert_eq!("b/c", apply_macros("[middle/a/b/c/d]".into(), &macros)?);
+assert_eq!("Loading a...", apply_macros("[loading/a/b]".into(), &macros)?);
+assert_eq!("Hello, world!", apply_macros("[greet]".into(), &macros)?);
+assert_eq!("Hello, you, from me!", apply_macros("[greet/you/me]".into(), &macros)?);
+assert_eq!("cats", apply_macros("[plural/cat/many]".into(), &macros)?);
+let deep = format!("{}x{}", "${1:".repeat(10_000), "}".repeat(10_000));
+assert_eq!("a", TextMacro::new(deep.as_str()).apply(vec!["a"])?);
+#        Ok(()) }
+```
+
+## Named parameters
+A text macro created with [`TextMacro::with_params`] has a signature, which is a list of parameter names.
+`$<name>` is replaced with the argument for that parameter, and a name can be used anywhere an index can,
+like `${name}` or `${name:fallback}`. Names are as long as possible, so use braces to put text right after one, like `${x}s`.
+
+Every parameter is required, and calling the macro with fewer arguments raises
+[`MacroErrorKind::NotEnoughArguments`] with the name of the first missing parameter.
+Any extra arguments can still be used by their index.
+
+In [definitions](parse_definitions), the parameters are listed in parentheses after the name, like `add3(x, y, z): ...`.
+
+```
+#    use macroscript::{Macro, Engine, TextMacro, add_stdlib};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::<String, Box<dyn Macro>>::from([
+    ("add3".to_string(), Box::new(TextMacro::with_params(["x", "y", "z"], "[add/$x/[add/$y/$z]]")) as Box<dyn Macro>),
+    ("things".to_string(), Box::new(TextMacro::with_params(["n", "thing"], "$n ${thing}s")))
+]);
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros);
+assert_eq!("6", engine.apply("[add3/1/2/3]".into())?);
+assert_eq!("3 cats", engine.apply("[things/3/cat]".into())?);
+let err = engine.apply("[add3/1/2]".into()).unwrap_err();
+assert_eq!("error in macro add3: expected 3 arguments, found 2, missing \"z\"", err.to_string());
 #        Ok(()) }
 ```
 
 ## Implementation Detail
-The character `\u{FFFF}` is used to replace escaped `$` where needed.
-This means any instances of those bytes in the string will be replaced with `$`.
+The pattern is compiled into a template once, when the macro is created, so applying a macro doesn't reparse it.
+The result is only rescanned if a replacement may have constructed a new string to replace.
+Rescanning stops once it no longer changes the result, after 16 rescans,
+or once the result is more than 16 times as long as it was before rescanning and the arguments together,
+so arguments that contain strings to replace can't make a macro run forever.
 */
 #[derive(Debug, Clone, PartialEq, Eq, Default, Hash)]
 pub struct TextMacro {
-	/// The pattern of the text macro.
-	pub pattern: String
+	pattern: String,
+	params: Vec<String>,
+	template: Vec<Segment>
 }
 
 impl TextMacro {
 	/// Creates a new text macro.
 	#[inline]
 	pub fn new(pattern: impl Into<String>) -> Self {
-		Self { pattern: pattern.into() }
+		Self::with_params(Vec::<String>::new(), pattern)
+	}
+
+	/// Creates a new text macro with named parameters. See [the section on them](TextMacro#named-parameters).
+	pub fn with_params<I: IntoIterator<Item = S>, S: Into<String>>(params: I, pattern: impl Into<String>) -> Self {
+		let pattern = pattern.into();
+		let params: Vec<String> = params.into_iter().map(Into::into).collect();
+		let template = compile(&pattern, true, &params, 0);
+		Self { pattern, params, template }
 	}
 
 	/// Creates a new text macro in a box. Mostly useful for directly adding to a [`std::collections::HashMap`].
 	#[inline]
 	pub fn boxed(pattern: impl Into<String>) -> Box<dyn Macro> {
-		Box::new(Self { pattern: pattern.into() })
+		Box::new(Self::new(pattern))
+	}
+
+	/// The pattern of the text macro.
+	///
+	/// This used to be a public field, and is read through this method since 0.7.
+	#[inline]
+	#[must_use]
+	pub fn pattern(&self) -> &str {
+		&self.pattern
+	}
+
+	/// Replaces the pattern of the text macro, keeping its parameters.
+	///
+	/// ## Example
+	/// ```
+	/// #    use macroscript::TextMacro;
+	/// let mut mac = TextMacro::new("[add/$1/$2]");
+	/// mac.set_pattern("[subtract/$1/$2]");
+	/// assert_eq!("[subtract/$1/$2]", mac.pattern());
+	/// ```
+	pub fn set_pattern(&mut self, pattern: impl Into<String>) {
+		self.pattern = pattern.into();
+		self.template = compile(&self.pattern, true, &self.params, 0);
+	}
+
+	/// The names of the parameters of the text macro, if it has any.
+	#[inline]
+	#[must_use]
+	pub fn params(&self) -> &[String] {
+		&self.params
 	}
 }
 
 impl From<String> for TextMacro {
 	fn from(pattern: String) -> Self {
-		Self {pattern}
+		Self::new(pattern)
 	}
 }
 
@@ -79,62 +197,420 @@ impl From<TextMacro> for String {
 	}
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum Substring {
+/// A part of a compiled pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+	/// Text that's output as-is.
+	Literal(String),
+	/// An escaped `$`, which is never part of a constructed string.
+	Dollar,
+	/// `$<selector>`, which is output as-is if the selected arguments don't exist.
+	Select(Selector),
+	/// `${<selector>:<fallback>}`
+	Default { selector: Selector, fallback: Vec<Segment> },
+	/// `${<selector>?<then>:<otherwise>}`
+	Present { selector: Selector, then: Vec<Segment>, otherwise: Vec<Segment> }
+}
+
+/// Which arguments a replaced string refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Selector {
+	/// `#`
 	Count,
-	Index(usize)
+	/// `<num>`, where 0 is all arguments and negative numbers count from the end.
+	Index(isize),
+	/// `<start>..<end>`, where negative numbers count from the end.
+	Range(isize, Option<isize>)
+}
+
+impl Selector {
+	/// Parses a selector from the start of a string, returning it and its length.
+	///
+	/// Names of parameters are turned into their indices, and ranges are only parsed if asked to.
+	fn parse(string: &str, params: &[String], ranges: bool) -> Option<(Self, usize)> {
+		match string.chars().next()? {
+			'#' => return Some((Selector::Count, 1)),
+			'0' => return Some((Selector::Index(0), 1)),
+			c if c.is_alphabetic() || c == '_' => {
+				let end = string.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(string.len());
+				let index = params.iter().position(|param| *param == string[..end])?;
+				return Some((Selector::Index(isize::try_from(index).ok()? + 1), end));
+			}
+			_ => {}
+		}
+		let (start, mut len) = parse_index(string)?;
+		let Some(rest) = string[len..].strip_prefix("..").filter(|_| ranges) else {
+			return Some((Selector::Index(start), len));
+		};
+		len += 2;
+		let end = parse_index(rest).map(|(end, end_len)| {
+			len += end_len;
+			end
+		});
+		Some((Selector::Range(start, end), len))
+	}
+
+	/// Gets the selected arguments, if they exist.
+	fn select<'args>(self, arguments: &[&'args str]) -> Option<Cow<'args, str>> {
+		let len = arguments.len();
+		// Converts a one-based index that may count from the end to a zero-based one
+		let resolve = |index: isize| if index < 0 {
+			len.checked_sub(index.unsigned_abs())
+		} else {
+			Some(index.unsigned_abs() - 1)
+		};
+		match self {
+			Selector::Count => Some(Cow::Owned(len.to_string())),
+			Selector::Index(0) => Some(Cow::Owned(arguments.join("/"))),
+			Selector::Index(index) => resolve(index).and_then(|index| arguments.get(index)).map(|arg| Cow::Borrowed(*arg)),
+			Selector::Range(start, end) => {
+				let start = resolve(start).unwrap_or(0).min(len);
+				let end = end.map_or(Some(len), resolve).unwrap_or(0).min(len);
+				Some(Cow::Owned(arguments.get(start .. end).unwrap_or_default().join("/")))
+			}
+		}
+	}
+
+	/// Returns whether the selected arguments exist.
+	fn exists(self, arguments: &[&str]) -> bool {
+		match self {
+			Selector::Range(..) => self.select(arguments).is_some_and(|args| !args.is_empty()),
+			_ => self.select(arguments).is_some()
+		}
+	}
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Selector::Count => write!(f, "#"),
+			Selector::Index(index) => write!(f, "{index}"),
+			Selector::Range(start, None) => write!(f, "{start}.."),
+			Selector::Range(start, Some(end)) => write!(f, "{start}..{end}")
+		}
+	}
+}
+
+/// Parses a nonzero index that may be negative from the start of a string, returning it and its length.
+fn parse_index(string: &str) -> Option<(isize, usize)> {
+	let negative = string.starts_with('-');
+	let digits = &string[usize::from(negative)..];
+	let end = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+	let index = isize::from_str(&digits[..end]).ok().filter(|&index| index != 0)?;
+	Some((if negative { -index } else { index }, usize::from(negative) + end))
 }
 
+/// How deeply braces may be nested inside each other. Any deeper braces are left as text.
+const MAX_BRACE_DEPTH: usize = 32;
+
+/// Finds the end of a branch of braces, returning the position of the first `:` (if wanted) and `}` that aren't nested.
+fn find_branch_end(string: &str, colon: bool) -> Option<(Option<usize>, usize)> {
+	let mut depth = 0usize;
+	let mut split = None;
+	let mut chars = string.char_indices().peekable();
+	while let Some((idx, chr)) = chars.next() {
+		match chr {
+			'$' if chars.peek().is_some_and(|&(_, next)| next == '{') => {
+				chars.next();
+				depth += 1;
+			}
+			'}' if depth == 0 => return Some((split, idx)),
+			'}' => depth -= 1,
+			':' if depth == 0 && colon && split.is_none() => split = Some(idx),
+			_ => {}
+		}
+	}
+	None
+}
+
+/// Parses the inside of braces after `${` nested in the given amount of others,
+/// returning the segment and the length including the closing brace.
+fn parse_braces(string: &str, escapes: bool, params: &[String], depth: usize) -> Option<(Segment, usize)> {
+	if depth >= MAX_BRACE_DEPTH {
+		return None;
+	}
+	let (selector, len) = Selector::parse(string, params, true)?;
+	let rest = &string[len..];
+	if rest.starts_with('}') {
+		return Some((Segment::Select(selector), len + 1));
+	}
+	if let Some(branch) = rest.strip_prefix(':') {
+		let (_, end) = find_branch_end(branch, false)?;
+		let fallback = compile(&branch[..end], escapes, params, depth + 1);
+		return Some((Segment::Default { selector, fallback }, len + 1 + end + 1));
+	}
+	let branch = rest.strip_prefix('?')?;
+	let (split, end) = find_branch_end(branch, true)?;
+	let (then, otherwise) = match split {
+		Some(split) => (&branch[..split], &branch[split + 1 .. end]),
+		None => (&branch[..end], "")
+	};
+	Some((
+		Segment::Present { selector, then: compile(then, escapes, params, depth + 1), otherwise: compile(otherwise, escapes, params, depth + 1) },
+		len + 1 + end + 1
+	))
+}
+
+/// A part of the output of a text macro.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+	/// Text that may be rescanned.
+	Text(String),
+	/// An escaped `$`.
+	Dollar
+}
 
 // In the Python version, a regular expression with a negative lookbehind with a backslash was used.
 // Unfortunately, Rust's regex library doesn't support lookarounds.
 // I've reimplemented this without regex entirely.
+/// Compiles a pattern nested in the given amount of braces into segments, optionally treating `\$` as an escaped `$`.
+fn compile(pattern: &str, escapes: bool, params: &[String], depth: usize) -> Vec<Segment> {
+	let mut segments = Vec::new();
+	let mut literal = String::new();
+	let mut rest = pattern;
+	while let Some(idx) = rest.find(['$', '\\']) {
+		literal.push_str(&rest[..idx]);
+		rest = &rest[idx..];
+		let segment = if let Some(after) = rest.strip_prefix('\\') {
+			if let (true, Some(after)) = (escapes, after.strip_prefix('$')) {
+				rest = after;
+				Segment::Dollar
+			} else {
+				literal.push('\\');
+				rest = after;
+				continue;
+			}
+		} else {
+			let after = &rest[1..];
+			let segment = match after.strip_prefix('{') {
+				Some(braces) => parse_braces(braces, escapes, params, depth).map(|(segment, len)| (segment, len + 1)),
+				None => Selector::parse(after, params, false).map(|(selector, len)| (Segment::Select(selector), len))
+			};
+			let Some((segment, len)) = segment else {
+				literal.push('$');
+				rest = after;
+				continue;
+			};
+			rest = &after[len..];
+			segment
+		};
+		if !literal.is_empty() {
+			segments.push(Segment::Literal(std::mem::take(&mut literal)));
+		}
+		segments.push(segment);
+	}
+	literal.push_str(rest);
+	if !literal.is_empty() {
+		segments.push(Segment::Literal(literal));
+	}
+	segments
+}
+
+/// Adds text to the output, merging it with the last piece if possible.
+fn push(pieces: &mut Vec<Piece>, text: &str) {
+	match pieces.last_mut() {
+		Some(Piece::Text(last)) => last.push_str(text),
+		_ => pieces.push(Piece::Text(text.to_string()))
+	}
+}
+
+/// Adds replaced text to the output, returning whether it may have constructed a new string to replace.
+fn substitute(pieces: &mut Vec<Piece>, text: &str) -> bool {
+	// Text after a `$` may extend it into a new string, even if it's empty and the text after it does instead
+	let extends = matches!(pieces.last(), Some(Piece::Text(last)) if last.contains('$'));
+	push(pieces, text);
+	extends || text.contains('$')
+}
+
+/// Renders segments with the given arguments, returning whether a new string to replace may have been constructed.
+fn render(segments: &[Segment], arguments: &[&str], pieces: &mut Vec<Piece>) -> bool {
+	let mut constructed = false;
+	for segment in segments {
+		constructed |= match segment {
+			Segment::Literal(text) => {
+				push(pieces, text);
+				false
+			}
+			Segment::Dollar => {
+				pieces.push(Piece::Dollar);
+				false
+			}
+			Segment::Select(selector) => if let Some(text) = selector.select(arguments) {
+				substitute(pieces, &text)
+			} else {
+				push(pieces, &format!("${selector}"));
+				false
+			}
+			Segment::Default { selector, fallback } => match selector.select(arguments) {
+				Some(text) => substitute(pieces, &text),
+				// The fallback was never replaced, so it can't have constructed anything yet
+				None => render_replaced(fallback, arguments, pieces)
+			}
+			Segment::Present { selector, then, otherwise } =>
+				render_replaced(if selector.exists(arguments) { then } else { otherwise }, arguments, pieces)
+		};
+	}
+	constructed
+}
+
+/// Renders segments that replace a string, returning whether a new string to replace may have been constructed.
+fn render_replaced(segments: &[Segment], arguments: &[&str], pieces: &mut Vec<Piece>) -> bool {
+	let extends = matches!(pieces.last(), Some(Piece::Text(last)) if last.contains('$'));
+	let start = pieces.len();
+	let constructed = render(segments, arguments, pieces);
+	constructed || extends || pieces[start.saturating_sub(1) ..].iter().any(|piece| matches!(piece, Piece::Text(text) if text.contains('$')))
+}
+
+impl TextMacro {
+	/// Replaces the strings in the pattern with the arguments, without rescanning the result,
+	/// so arguments are always inserted as-is.
+	pub(crate) fn substitute(&self, arguments: &[&str]) -> String {
+		let mut pieces = Vec::new();
+		render(&self.template, arguments, &mut pieces);
+		finish(pieces)
+	}
+}
+
+/// How many times the output of a text macro may be rescanned.
+const MAX_RESCANS: usize = 16;
+
+/// How many times longer than its first output and arguments together the output of a text macro may grow
+/// before it stops being rescanned.
+const MAX_GROWTH: usize = 16;
+
+/// Finds the length of the pieces of an output, in bytes.
+fn length(pieces: &[Piece]) -> usize {
+	pieces.iter().map(|piece| match piece {
+		Piece::Text(text) => text.len(),
+		Piece::Dollar => 1
+	}).sum()
+}
+
+/// Joins the pieces of an output into a string.
+fn finish(pieces: Vec<Piece>) -> String {
+	pieces.into_iter().map(|piece| match piece {
+		Piece::Text(text) => Cow::Owned(text),
+		Piece::Dollar => Cow::Borrowed("$")
+	}).collect()
+}
+
 impl Macro for TextMacro {
 	fn apply(&self, arguments: Vec<&str>) -> Result<String, MacroError> {
-		let amount = LazyCell::new(|| arguments.len().to_string());
-		let joined = LazyCell::new(|| arguments.join("/"));
-		let mut target = self.pattern.clone();
-		// Find instance of $# or $\d+, but not \$, from back
-		// We use an auxiliary character to weed out the `\$`s.
-		target = target.replace(r"\$", "\u{FFFF}");
-		let mut none_found = false;
-		let mut subs = Vec::new();
-		while !none_found {
-			none_found = true;
-			subs.clear();
-			let mut passed = target.clone();
-			for (idx, _) in passed.rmatch_indices('$') {
-				let after_index = &passed[idx+1..];
-				let (sub, end) = match after_index.chars().next() {
-					Some('#') => (Substring::Count, idx + 2),
-					Some('0') => (Substring::Index(0), idx + 2),
-					Some(c) if c.is_ascii_digit()  => {
-						let end = after_index.find(|c: char| !c.is_ascii_digit()).unwrap_or(after_index.len());
-						let Some(index) = usize::from_str(&after_index[..end])
-							.ok()
-							.filter(|v| arguments.len() >= *v)
-						else { continue };
-						(Substring::Index(index), end + 1)
-					},
-					_ => { continue }
-				};
-				subs.push((idx .. idx + end, sub));
+		if let Some(missing) = self.params.get(arguments.len()) {
+			// The engine fills in the name this was called with
+			return Err(MacroError::new(
+				String::new(), MacroErrorKind::missing_parameter(self.params.len(), arguments.len(), missing)
+			));
+		}
+		let mut pieces = Vec::new();
+		let mut constructed = render(&self.template, &arguments, &mut pieces);
+		let max_len = arguments.iter().map(|arg| arg.len()).fold(length(&pieces), usize::saturating_add).saturating_mul(MAX_GROWTH);
+		// Replacing strings may have constructed new ones, so keep going until nothing changes
+		for _ in 0..MAX_RESCANS {
+			if !constructed || length(&pieces) > max_len {
+				break;
 			}
-			for (range, substring) in subs.drain(..) {
-				let repl: Cow<'_, str> = match substring {
-					Substring::Count => Cow::Borrowed(&*amount),
-					Substring::Index(0) => Cow::Borrowed(&*joined),
-					Substring::Index(n) => if let Some(arg) = arguments.get(n-1) {
-						Cow::Borrowed(&**arg)
-					} else { continue }
+			constructed = false;
+			let mut next = Vec::with_capacity(pieces.len());
+			for piece in &pieces {
+				match piece {
+					Piece::Text(text) if text.contains('$') =>
+						constructed |= render(&compile(text, false, &self.params, 0), &arguments, &mut next),
+					Piece::Text(text) => push(&mut next, text),
+					Piece::Dollar => next.push(Piece::Dollar)
+				}
+			}
+			// An argument that contains its own string to replace would otherwise be replaced forever
+			if next == pieces {
+				break;
+			}
+			pieces = next;
+		}
+		Ok(finish(pieces))
+	}
+}
+
+/**
+Parses text macro definitions, one per line, returning the name and macro of each.
+
+Each line is either `name: pattern`, or `name(param, ...): pattern` for a macro with [named parameters](TextMacro#named-parameters).
+Whitespace after the colon and blank lines are ignored.
+
+## Example
+```
+#    use macroscript::{Macro, Engine, add_stdlib, textmacro::parse_definitions};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let definitions = "
+double: [multiply/$1/2]
+add3(x, y, z): [add/$x/[add/$y/$z]]
+";
+let mut macros = HashMap::<String, Box<dyn Macro>>::new();
+add_stdlib(&mut macros);
+for (name, mac) in parse_definitions(definitions)? {
+    macros.insert(name, Box::new(mac));
+}
+assert_eq!("12", Engine::new(&macros).apply("[double/[add3/1/2/3]]".into())?);
+let err = parse_definitions("ok: fine\nbad(x, x): $x").unwrap_err();
+assert_eq!("invalid text macro definition on line 2: duplicate parameter", err.to_string());
+#        Ok(()) }
+```
+
+# Errors
+Errors with the first line that isn't a valid definition.
+*/
+pub fn parse_definitions(source: &str) -> Result<Vec<(String, TextMacro)>, DefinitionError> {
+	let mut definitions = Vec::new();
+	for (idx, line) in source.lines().enumerate() {
+		if line.trim().is_empty() { continue }
+		let error = |reason| DefinitionError { line: idx + 1, reason };
+		let Some((signature, pattern)) = line.split_once(':') else {
+			return Err(error("missing colon"));
+		};
+		let (name, params) = match signature.split_once('(') {
+			Some((name, params)) => {
+				let Some(params) = params.trim_end().strip_suffix(')') else {
+					return Err(error("unclosed parameter list"));
 				};
-				none_found = false;
-				dbg!(&passed, &range, &repl);
-				passed.replace_range(range, &repl);
+				let params: Vec<&str> = params.split(',').map(str::trim).filter(|param| !param.is_empty()).collect();
+				if !params.iter().all(|param| is_identifier(param)) {
+					return Err(error("invalid parameter name"));
+				}
+				if params.iter().enumerate().any(|(idx, param)| params[..idx].contains(param)) {
+					return Err(error("duplicate parameter"));
+				}
+				(name.trim(), params)
 			}
-			target = passed;
+			None => (signature.trim(), Vec::new())
+		};
+		if name.is_empty() || name.contains(|c: char| c.is_whitespace() || "[]/\\()".contains(c)) {
+			return Err(error("invalid macro name"));
 		}
-		target = target.replace('\u{FFFF}', "$");
-		Ok(target)
+		definitions.push((name.to_string(), TextMacro::with_params(params, pattern.trim_start())));
+	}
+	Ok(definitions)
+}
+
+/// Returns whether a string can be used as the name of a parameter.
+fn is_identifier(string: &str) -> bool {
+	string.starts_with(|c: char| c.is_alphabetic() || c == '_')
+		&& string.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// An invalid line passed to [`parse_definitions`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DefinitionError {
+	/// The line number, starting at 1.
+	pub line: usize,
+	/// Why the line is invalid.
+	pub reason: &'static str
+}
+
+impl std::error::Error for DefinitionError {}
+
+impl std::fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "invalid text macro definition on line {}: {}", self.line, self.reason)
 	}
 }
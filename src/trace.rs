@@ -0,0 +1,329 @@
+/*!
+Contains items for recording how a script was reduced.
+
+If you want to visualize an evaluation, see the documentation of [`ExpansionTree`].
+*/
+
+use std::{collections::HashMap, fmt::Write, ops::Range};
+use crate::MacroError;
+
+/**
+A tree of the macro calls made during an [`crate::Evaluation`] with [tracing](crate::Evaluation::tracing) on.
+
+Each node is a call. Its parent is the call whose output contained it,
+such as a `map` whose output called a macro once for each item,
+or the `try` it was evaluated inside of. Calls written in the input itself have no parent.
+
+A call whose brackets were put together from the output of several calls
+belongs to the innermost call whose output contained all of it.
+
+The tree can be exported to JSON with [`ExpansionTree::to_json`], and to Graphviz DOT with [`ExpansionTree::to_dot`].
+
+## Example
+```
+#    use macroscript::{Engine, add_stdlib};
+#    use std::collections::HashMap;
+#
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let mut macros = HashMap::new();
+add_stdlib(&mut macros);
+let engine = Engine::new(&macros);
+let mut evaluation = engine.evaluate(r"[unescape/\[add\/1\/\[add\/2\/3\]\]]".into()).tracing(true);
+evaluation.run()?;
+let tree = evaluation.expansion_tree().unwrap();
+let unescape = &tree.nodes()[0];
+assert_eq!(("unescape", None), (unescape.name.as_str(), unescape.parent));
+let [inner, outer] = unescape.children[..] else { panic!() };
+assert_eq!((Some("5"), Some(0)), (tree.nodes()[inner].output.as_deref(), tree.nodes()[inner].parent));
+assert_eq!((Some("6"), Some(0)), (tree.nodes()[outer].output.as_deref(), tree.nodes()[outer].parent));
+assert!(tree.to_dot().contains("node0 -> node1;"));
+assert!(tree.to_json().starts_with(r#"[{"name":"unescape","arguments":["#));
+#        Ok(()) }
+```
+*/
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Hash)]
+pub struct ExpansionTree {
+	nodes: Vec<ExpansionNode>
+}
+
+/// A single macro call in an [`ExpansionTree`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExpansionNode {
+	/// The name the macro was called with.
+	pub name: String,
+	/// The arguments of the call.
+	pub arguments: Vec<String>,
+	/// What the call was replaced with, or `None` if it failed or never finished.
+	pub output: Option<String>,
+	/// The error the call raised, if any.
+	pub error: Option<MacroError>,
+	/// The index of the call whose output contained this call, if any.
+	pub parent: Option<usize>,
+	/// The indices of the calls this call's output contained, in the order they were made.
+	pub children: Vec<usize>
+}
+
+impl ExpansionTree {
+	/// All calls, in the order they were made.
+	#[must_use]
+	pub fn nodes(&self) -> &[ExpansionNode] {
+		&self.nodes
+	}
+
+	/// The indices of the calls that were written in the input itself, in the order they were made.
+	pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+		self.nodes.iter().enumerate().filter(|(_, node)| node.parent.is_none()).map(|(idx, _)| idx)
+	}
+
+	/// Exports the tree to JSON, as a list of the root calls.
+	///
+	/// Each call is an object with the fields `name`, `arguments`, `output`, `error` and `children`,
+	/// where `output` and `error` may be `null`, and `children` is a list of calls.
+	#[must_use]
+	pub fn to_json(&self) -> String {
+		let mut json = String::from("[");
+		for (idx, root) in self.roots().enumerate() {
+			if idx > 0 { json.push(','); }
+			self.write_json(&mut json, root);
+		}
+		json.push(']');
+		json
+	}
+
+	/// Writes a call and its children as a JSON object.
+	fn write_json(&self, json: &mut String, idx: usize) {
+		let node = &self.nodes[idx];
+		json.push_str("{\"name\":");
+		write_json_string(json, &node.name);
+		json.push_str(",\"arguments\":[");
+		for (idx, argument) in node.arguments.iter().enumerate() {
+			if idx > 0 { json.push(','); }
+			write_json_string(json, argument);
+		}
+		json.push_str("],\"output\":");
+		match &node.output {
+			Some(output) => write_json_string(json, output),
+			None => json.push_str("null")
+		}
+		json.push_str(",\"error\":");
+		match &node.error {
+			Some(error) => write_json_string(json, &error.to_string()),
+			None => json.push_str("null")
+		}
+		json.push_str(",\"children\":[");
+		for (idx, &child) in node.children.iter().enumerate() {
+			if idx > 0 { json.push(','); }
+			self.write_json(json, child);
+		}
+		json.push_str("]}");
+	}
+
+	/// Exports the tree to a Graphviz DOT digraph, with an edge from each call to the calls its output contained.
+	#[must_use]
+	pub fn to_dot(&self) -> String {
+		let mut dot = String::from("digraph expansion {\n\tnode [shape=box];\n");
+		for (idx, node) in self.nodes.iter().enumerate() {
+			let mut label = node.name.clone();
+			for argument in &node.arguments {
+				label.push('/');
+				label.push_str(argument);
+			}
+			match (&node.output, &node.error) {
+				(Some(output), _) => { let _ = write!(label, "\n= {output}"); }
+				(None, Some(error)) => { let _ = write!(label, "\n! {error}"); }
+				(None, None) => {}
+			}
+			let _ = writeln!(dot, "\tnode{idx} [label=\"{}\"];", escape_dot(&label));
+		}
+		for (idx, node) in self.nodes.iter().enumerate() {
+			for child in &node.children {
+				let _ = writeln!(dot, "\tnode{idx} -> node{child};");
+			}
+		}
+		dot.push('}');
+		dot
+	}
+}
+
+/// Writes a string as a quoted JSON string.
+fn write_json_string(json: &mut String, string: &str) {
+	json.push('"');
+	for chr in string.chars() {
+		match chr {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			'\n' => json.push_str("\\n"),
+			'\r' => json.push_str("\\r"),
+			'\t' => json.push_str("\\t"),
+			chr if chr.is_control() => { let _ = write!(json, "\\u{:04x}", u32::from(chr)); }
+			chr => json.push(chr)
+		}
+	}
+	json.push('"');
+}
+
+/// Escapes a string for use inside a quoted DOT label.
+fn escape_dot(string: &str) -> String {
+	string.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+/// Keeps track of which call produced each part of the strings being rewritten,
+/// to find the ancestry of calls and build an [`ExpansionTree`] if tracing.
+#[derive(Debug)]
+pub(crate) struct Tracer {
+	/// The tree being recorded, if tracing.
+	pub(crate) tree: Option<ExpansionTree>,
+	/// For each call, the index of its name in `names` and its parent.
+	calls: Vec<(usize, Option<usize>)>,
+	/// The name of each macro that was called.
+	names: Vec<String>,
+	/// The index of each name in `names`.
+	indices: HashMap<String, usize>,
+	/// For each frame above the root, the call that created it.
+	open: Vec<usize>,
+	/// For each frame, the byte ranges that were produced by a call.
+	spans: Vec<Vec<(Range<usize>, usize)>>,
+	/// The call the evaluation is suspended at, if any.
+	pub(crate) suspended: Option<usize>
+}
+
+impl Tracer {
+	/// Creates a tracer for an evaluation with the given amount of frames, which records a tree if asked to.
+	pub(crate) fn new(frames: usize, tree: bool) -> Self {
+		Self {
+			tree: tree.then(ExpansionTree::default),
+			calls: Vec::new(),
+			names: Vec::new(),
+			indices: HashMap::new(),
+			open: Vec::new(),
+			spans: vec![Vec::new(); frames],
+			suspended: None
+		}
+	}
+
+	/// Records a call at a range of the innermost frame, returning its index.
+	pub(crate) fn call(&mut self, range: &Range<usize>, name: &str, arguments: &[&str]) -> usize {
+		let parent = self.spans.last()
+			.into_iter()
+			.flatten()
+			.rev()
+			.filter(|(span, _)| span.start <= range.start && span.end >= range.end)
+			.min_by_key(|(span, _)| span.len())
+			.map(|&(_, node)| node)
+			.or(self.open.last().copied());
+		let idx = self.calls.len();
+		let name_idx = self.intern(name);
+		self.calls.push((name_idx, parent));
+		if let Some(tree) = &mut self.tree {
+			tree.nodes.push(ExpansionNode {
+				name: name.to_string(),
+				arguments: arguments.iter().map(ToString::to_string).collect(),
+				output: None,
+				error: None,
+				parent,
+				children: Vec::new()
+			});
+			if let Some(parent) = parent {
+				tree.nodes[parent].children.push(idx);
+			}
+		}
+		idx
+	}
+
+	/// Finds the index of a name in `names`, adding it if it's new.
+	fn intern(&mut self, name: &str) -> usize {
+		*self.indices.entry(name.to_string()).or_insert_with(|| {
+			self.names.push(name.to_string());
+			self.names.len() - 1
+		})
+	}
+
+	/// Records that a call ran another macro in its place, so its ancestry names that macro instead.
+	/// The tree still shows the name it was called with.
+	pub(crate) fn dispatch(&mut self, node: usize, name: &str) {
+		self.calls[node].0 = self.intern(name);
+	}
+
+	/// Finds the cycle a call is part of, if it's nested inside calls to the same macro more than the given amount of times.
+	///
+	/// The cycle goes from the innermost enclosing call to the same macro down to the call itself.
+	pub(crate) fn cycle(&self, node: usize, limit: usize) -> Option<Vec<String>> {
+		let (name, mut parent) = self.calls[node];
+		let mut nearest = None;
+		let mut depth = 0;
+		while let Some(ancestor) = parent {
+			let (other, next) = self.calls[ancestor];
+			if other == name {
+				depth += 1;
+				nearest.get_or_insert(ancestor);
+			}
+			parent = next;
+		}
+		if depth <= limit {
+			return None;
+		}
+		let nearest = nearest?;
+		let mut cycle = vec![self.names[name].clone()];
+		let mut current = node;
+		while current != nearest {
+			let Some(parent) = self.calls[current].1 else { unreachable!("the nearest call is an ancestor") };
+			cycle.push(self.names[self.calls[parent].0].clone());
+			current = parent;
+		}
+		cycle.reverse();
+		Some(cycle)
+	}
+
+	/// Records the result of a call.
+	pub(crate) fn finish(&mut self, node: usize, result: Result<&str, &MacroError>) {
+		let Some(tree) = &mut self.tree else { return };
+		let node = &mut tree.nodes[node];
+		match result {
+			Ok(output) => node.output = Some(output.to_string()),
+			Err(error) => node.error = Some(error.clone())
+		}
+	}
+
+	/// Records that a range of the innermost frame was replaced with a string of the given length, produced by a call.
+	pub(crate) fn replace(&mut self, range: &Range<usize>, len: usize, node: usize) {
+		let Some(spans) = self.spans.last_mut() else { unreachable!("the root frame is never popped") };
+		let shift = |pos: usize, inside: usize| if pos <= range.start {
+			pos
+		} else if pos >= range.end {
+			pos - range.len() + len
+		} else {
+			inside
+		};
+		for (span, _) in spans.iter_mut() {
+			*span = shift(span.start, range.start + len) .. shift(span.end, range.start);
+		}
+		spans.retain(|(span, _)| span.start < span.end);
+		spans.push((range.start .. range.start + len, node));
+	}
+
+	/// Records that a call pushed a new frame.
+	pub(crate) fn push(&mut self, node: usize) {
+		self.spans.push(Vec::new());
+		self.open.push(node);
+	}
+
+	/// The amount of frames being tracked.
+	pub(crate) fn depth(&self) -> usize {
+		self.spans.len()
+	}
+
+	/// Stops tracking all frames after the given amount.
+	pub(crate) fn truncate(&mut self, depth: usize) {
+		while self.spans.len() > depth {
+			self.pop();
+		}
+	}
+
+	/// Records that the innermost frame was popped, returning the call that created it.
+	pub(crate) fn pop(&mut self) -> Option<usize> {
+		self.spans.pop();
+		self.open.pop()
+	}
+}